@@ -1,8 +1,8 @@
 use crate::tree::*;
 use crate::state;
-use crate::system::ShowSystem;
+use crate::system::{MotionSystem, ForceSystem, OrbitCameraSystem, PickSystem, SelectionSystem, SearchSystem, LabelSystem};
 use crate::state::GodsPrefabData;
-use serde_json::Value;
+use crate::cli::ViewOptions;
 
 use amethyst:: {
     Error,
@@ -20,31 +20,41 @@ use amethyst:: {
 };
 
 
-pub fn run(raw: &Value) -> Result<(), Error> {
+pub fn run(woods: Forest, options: ViewOptions) -> Result<(), Error> {
     amethyst::start_logger(Default::default());
     let app_root = utils::application_root_dir()?;
 
     let asset_dir = app_root.join("assets");
-    let display_config_path = app_root.join("config/display.ron");
+    let display_config_path = options.display.clone().unwrap_or_else(|| app_root.join("config/display.ron"));
+    let bindings_path = options.bindings.clone().unwrap_or_else(|| app_root.join("config/input.ron"));
     let game_data = GameDataBuilder::default()
         .with_system_desc(assets::PrefabLoaderSystemDesc::<GodsPrefabData>::default(), "", &[])
-        .with(ShowSystem::default(), "show_system", &[])
+        .with(MotionSystem::default(), "motion_system", &[])
+        .with(ForceSystem::default(), "force_system", &["motion_system"])
+        .with(OrbitCameraSystem::default(), "orbit_camera_system", &[])
+        .with(PickSystem::default(), "pick_system", &["orbit_camera_system"])
+        .with(SelectionSystem::default(), "selection_system", &["pick_system"])
+        .with(SearchSystem::default(), "search_system", &["selection_system"])
+        .with(LabelSystem::default(), "label_system", &["orbit_camera_system", "selection_system"])
         .with_bundle(utils::fps_counter::FpsCounterBundle::default())?
         .with_bundle(
-            input::InputBundle::<input::StringBindings>::new().with_bindings_from_file(app_root.join("config/input.ron"))?,
+            input::InputBundle::<input::StringBindings>::new().with_bindings_from_file(bindings_path)?,
         )?
         .with_bundle(core::transform::TransformBundle::new())?
         .with_bundle(ui::UiBundle::<input::StringBindings>::new())?
         .with_bundle(renderer::RenderingBundle::<renderer::types::DefaultBackend>::new()
                      .with_plugin(renderer::plugins::RenderToWindow::from_config_path(display_config_path)
-                                  .with_clear([0.01, 0.03, 0.03, 1.0]))
+                                  .with_clear(options.theme.clear))
                      .with_plugin(renderer::plugins::RenderShaded3D::default())
                      .with_plugin(ui::RenderUi::default())
                      .with_plugin(plugins::RenderDebugLines::default())
                      .with_plugin(plugins::RenderSkybox::default())
         )?;
 
-    let mut game = Application::build(asset_dir, state::Loading::new(raw))?.build(game_data)?;
+    let collapsed = options.depth.map(|depth| woods.expand_to(depth)).unwrap_or_default();
+    let mut game = Application::build(asset_dir, state::Loading::new(woods, options.layout, collapsed, options.focus, options.reload, options.statuses))?
+        .with_resource(options.theme)
+        .build(game_data)?;
     game.run();
     Ok(())
 }
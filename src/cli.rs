@@ -0,0 +1,247 @@
+use crate::tree::*;
+use crate::misc::*;
+use crate::config::*;
+use crate::export::*;
+use crate::diff::{self, DiffFormat, DiffStatus};
+use crate::input::InputFormat;
+use crate::resource::Theme;
+use crate::svg::Projection;
+use crate::app;
+use crate::watch::{self, Reload};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use clap::ArgMatches;
+
+// Exit codes, usage errors exit with 1 through clap
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_INVALID: i32 = 2;
+pub const EXIT_FAILED: i32 = 3;
+
+pub struct ViewOptions {
+    // Display and input binding configs, default to the ones under config/
+    pub display: Option<PathBuf>,
+    pub bindings: Option<PathBuf>,
+    pub theme: Theme,
+    pub layout: LayoutConfig,
+    // Nodes deeper than this start collapsed
+    pub depth: Option<usize>,
+    // Search run at start, the camera flies to the best match
+    pub focus: Option<String>,
+    // Woods parsed again after their files changed
+    pub reload: Option<Receiver<Reload>>,
+    // Changes of the nodes of a diff by path, empty otherwise
+    pub statuses: HashMap<String, DiffStatus>,
+}
+
+pub fn read_source(matches: &ArgMatches) -> ForestSource {
+    ForestSource {
+        files: matches.values_of("input").map(|files| files.map(PathBuf::from).collect()).unwrap_or_default(),
+        config: PathBuf::from(matches.value_of("config").unwrap_or("./config.json")),
+        format: matches.value_of("input-format").and_then(InputFormat::parse),
+        layout: matches.value_of("layout").and_then(LayoutKind::parse),
+        orientation: matches.value_of("orientation").and_then(Orientation::parse),
+    }
+}
+
+fn read_woods(matches: &ArgMatches) -> Result<(ForestConfig, Forest), i32> {
+    load_source(&read_source(matches))
+}
+
+fn load_source(source: &ForestSource) -> Result<(ForestConfig, Forest), i32> {
+    // Files which cannot be read are a failure, not invalid woods
+    let forest = source.read().map_err(|e| {
+        eprintln!("{}", e);
+        if e.is_io() { EXIT_FAILED } else { EXIT_INVALID }
+    })?;
+    let woods = Forest::load(&forest).map_err(|e| {
+        eprintln!("Failed to parse wood: {}", e);
+        EXIT_INVALID
+    })?;
+    Ok((forest, woods))
+}
+
+pub fn view(matches: &ArgMatches) -> i32 {
+    let (forest, woods) = match read_woods(matches) {
+        Ok(woods) => woods,
+        Err(code) => return code,
+    };
+
+    let mut options = ViewOptions {
+        display: matches.value_of("display").map(PathBuf::from),
+        bindings: matches.value_of("bindings").map(PathBuf::from),
+        theme: Theme::default(),
+        layout: forest.layout.clone(),
+        depth: None,
+        focus: matches.value_of("focus").map(|focus| focus.to_string()),
+        reload: None,
+        statuses: HashMap::new(),
+    };
+    if let Some(theme) = matches.value_of("theme") {
+        options.theme = Theme::by_name(theme).unwrap();
+    }
+    if let Some(depth) = matches.value_of("depth") {
+        match depth.parse::<usize>() {
+            Ok(depth) if depth > 0 => options.depth = Some(depth),
+            _ => {
+                eprintln!("Invalid depth {}, expected a positive number of levels", depth);
+                return EXIT_INVALID;
+            }
+        }
+    }
+    if matches.is_present("watch") {
+        options.reload = Some(watch::watch(read_source(matches), forest.files.clone()));
+    }
+
+    match app::run(woods, options) {
+        Ok(_) => EXIT_OK,
+        Err(e) => {
+            eprintln!("Viewer failed: {}", e);
+            EXIT_FAILED
+        }
+    }
+}
+
+pub fn stats(matches: &ArgMatches) -> i32 {
+    let (_, woods) = match read_woods(matches) {
+        Ok(woods) => woods,
+        Err(code) => return code,
+    };
+
+    for wood in woods.ordered().iter() {
+        let wood = wood.read().unwrap();
+        let stats = wood.stats();
+        println!("wood {}", wood.wood.read_name());
+        println!("  nodes: {}", stats.nodes);
+        println!("  leaves: {}", stats.leaves);
+        println!("  depth: {}", stats.depth);
+        println!("  max children: {}", stats.max_children);
+        println!("  links: {}", stats.links);
+    }
+    EXIT_OK
+}
+
+// Layout, camera and theme of svg drawings
+fn export_options(matches: &ArgMatches, forest: &ForestConfig, format: ExportFormat) -> Result<ExportOptions, i32> {
+    let mut options = ExportOptions {
+        format,
+        layout: forest.layout.clone(),
+        // Flat layouts are seen from the front
+        projection: if forest.layout.kind.is_flat() {
+            Projection::parse("front").unwrap()
+        } else {
+            Projection::top()
+        },
+        theme: Theme::default(),
+        statuses: HashMap::new(),
+    };
+    if let Some(camera) = matches.value_of("camera") {
+        match Projection::parse(camera) {
+            Some(projection) => options.projection = projection,
+            None => {
+                eprintln!("Invalid camera {}, expected top, front, side or <yaw>,<pitch>", camera);
+                return Err(EXIT_INVALID);
+            }
+        }
+    }
+    if let Some(theme) = matches.value_of("theme") {
+        options.theme = Theme::by_name(theme).unwrap();
+    }
+    Ok(options)
+}
+
+fn write_output(matches: &ArgMatches, output: String) -> i32 {
+    match matches.value_of("output") {
+        Some(path) => {
+            if let Err(e) = fs::write(path, output) {
+                eprintln!("Failed to write {}: {}", path, e);
+                return EXIT_FAILED;
+            }
+        },
+        None => print!("{}", output),
+    }
+    EXIT_OK
+}
+
+pub fn export(matches: &ArgMatches) -> i32 {
+    let (forest, woods) = match read_woods(matches) {
+        Ok(woods) => woods,
+        Err(code) => return code,
+    };
+
+    let format = ExportFormat::parse(matches.value_of("format").unwrap_or("json")).unwrap();
+    let options = match export_options(matches, &forest, format) {
+        Ok(options) => options,
+        Err(code) => return code,
+    };
+    write_output(matches, crate::export::export(&woods, &options))
+}
+
+// Compare two inputs, report the differences and optionally show the merged wood
+pub fn diff(matches: &ArgMatches) -> i32 {
+    let mut source = read_source(matches);
+    let mut versions = Vec::new();
+    for input in ["old", "new"].iter() {
+        source.files = vec![PathBuf::from(matches.value_of(input).unwrap())];
+        match load_source(&source) {
+            Ok(version) => versions.push(version),
+            Err(code) => return code,
+        }
+    }
+    let (forest, new) = versions.pop().unwrap();
+    let (_, old) = versions.pop().unwrap();
+
+    let mut diff = diff::diff(&old, &new);
+    diff.merged.arrangement = forest.arrangement.clone();
+    diff.merged.arrange(&forest.layout);
+
+    let output = match DiffFormat::parse(matches.value_of("format").unwrap_or("text")).unwrap() {
+        DiffFormat::Text => diff::report_text(&diff),
+        DiffFormat::Json => serde_json::to_string_pretty(&diff::report_json(&diff)).unwrap_or_default() + "\n",
+        DiffFormat::Svg => match export_options(matches, &forest, ExportFormat::Svg) {
+            Ok(mut options) => {
+                options.statuses = diff.statuses.clone();
+                crate::export::export(&diff.merged, &options)
+            },
+            Err(code) => return code,
+        },
+    };
+    let code = write_output(matches, output);
+    if code != EXIT_OK || !matches.is_present("view") {
+        return code;
+    }
+
+    let mut options = ViewOptions {
+        display: matches.value_of("display").map(PathBuf::from),
+        bindings: matches.value_of("bindings").map(PathBuf::from),
+        theme: Theme::default(),
+        layout: forest.layout.clone(),
+        depth: None,
+        focus: None,
+        reload: None,
+        statuses: diff.statuses,
+    };
+    if let Some(theme) = matches.value_of("theme") {
+        options.theme = Theme::by_name(theme).unwrap();
+    }
+    match app::run(diff.merged, options) {
+        Ok(_) => EXIT_OK,
+        Err(e) => {
+            eprintln!("Viewer failed: {}", e);
+            EXIT_FAILED
+        }
+    }
+}
+
+pub fn validate(matches: &ArgMatches) -> i32 {
+    let (_, woods) = match read_woods(matches) {
+        Ok(woods) => woods,
+        Err(code) => return code,
+    };
+
+    let nodes: usize = woods.ordered().iter().map(|wood| wood.read().unwrap().stats().nodes).sum();
+    println!("OK: {} woods, {} nodes", woods.names.len(), nodes);
+    EXIT_OK
+}
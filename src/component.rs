@@ -1,11 +1,19 @@
 use crate::node::*;
 use crate::tree::*;
 use std::sync::{Arc, RwLock};
-use amethyst::ecs::{Component, DenseVecStorage };
+use amethyst::ecs::{Component, DenseVecStorage, Entity};
+use amethyst::core::math::Vector3;
+use std::f32::consts::FRAC_PI_3;
+
+// Distance of orthographic cameras from the plane
+pub const ORBIT_FLAT_DISTANCE: f32 = 500.0;
+// Room left around fitted boxes, as a fraction of their size
+const ORBIT_FIT_MARGIN: f32 = 0.1;
+// Smallest half size of a fitted box, single nodes keep their neighbours in view
+const ORBIT_FIT_MIN: f32 = 5.0;
 
 
 pub type GodsNode = Godspoint<NodeProto>;
-pub type Forest = Godswoods<NodeProto, TreeProto>;
 
 pub struct Godspoint<N> where N: GodsnodeProto + 'static + Send + Sync {
     pub node: Arc<Godsnode<N>>,
@@ -23,3 +31,127 @@ impl<N: GodsnodeProto + 'static + Send + Sync> Default for Godspoint<N> {
         }
     }
 }
+
+// Moves the entity smoothly to the target translation
+pub struct Motion {
+    pub target: Vector3<f32>,
+    // Fraction of the remaining distance covered per second
+    pub speed: f32,
+}
+
+impl Motion {
+    pub fn new(target: Vector3<f32>) -> Self {
+        Self {
+            target,
+            speed: 4.0,
+        }
+    }
+}
+
+impl Component for Motion {
+    type Storage = DenseVecStorage<Self>;
+}
+
+// Node taking part in the force layout
+pub struct ForceNode {
+    pub velocity: Vector3<f32>,
+    // Height of the depth plane of the node
+    pub level: f32,
+}
+
+impl ForceNode {
+    pub fn new(level: f32) -> Self {
+        Self {
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            level,
+        }
+    }
+}
+
+impl Component for ForceNode {
+    type Storage = DenseVecStorage<Self>;
+}
+
+// Camera circling a target point, moves ease from the current values towards the goals
+pub struct OrbitCamera {
+    pub target: Vector3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub goal_target: Vector3<f32>,
+    pub goal_yaw: f32,
+    pub goal_pitch: f32,
+    pub goal_distance: f32,
+    // Orthographic cameras do not rotate, they zoom by scaling the view
+    pub flat: bool,
+    // Half the height of the view of an orthographic camera at its initial zoom
+    pub half_height: f32,
+}
+
+impl OrbitCamera {
+    pub fn new(target: Vector3<f32>, yaw: f32, pitch: f32, distance: f32, flat: bool) -> Self {
+        Self {
+            target,
+            yaw,
+            pitch,
+            distance,
+            goal_target: target,
+            goal_yaw: yaw,
+            goal_pitch: pitch,
+            goal_distance: distance,
+            flat,
+            half_height: 0.0,
+        }
+    }
+
+    // Aim at a box of the scene so it fills the view, for a view width over height of aspect
+    pub fn fit(&mut self, min: Vector3<f32>, max: Vector3<f32>, aspect: f32) {
+        let center = (min + max) / 2.0;
+        let size = max - min;
+        if self.flat {
+            let half_height = (size.y / 2.0).max(size.x / 2.0 / aspect).max(ORBIT_FIT_MIN) * (1.0 + ORBIT_FIT_MARGIN);
+            self.goal_target = Vector3::new(center.x, center.y, 0.0);
+            if self.half_height > 0.0 && half_height > 0.0 {
+                self.goal_distance = ORBIT_FLAT_DISTANCE * half_height / self.half_height;
+            }
+        } else {
+            // Keep the bounding sphere inside the narrower of both fields of view
+            let radius = (size.norm() / 2.0).max(ORBIT_FIT_MIN) * (1.0 + ORBIT_FIT_MARGIN);
+            let vertical = FRAC_PI_3 / 2.0;
+            let horizontal = (vertical.tan() * aspect).atan();
+            self.goal_target = center;
+            self.goal_distance = radius / vertical.min(horizontal).sin();
+        }
+    }
+
+    // Jump to the goals without easing
+    pub fn snap(&mut self) {
+        self.target = self.goal_target;
+        self.yaw = self.goal_yaw;
+        self.pitch = self.goal_pitch;
+        self.distance = self.goal_distance;
+    }
+
+    // Position of the camera around the target
+    pub fn eye(&self) -> Vector3<f32> {
+        let (yaw, pitch) = (self.yaw, self.pitch);
+        self.target + Vector3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos()) * self.distance
+    }
+}
+
+impl Component for OrbitCamera {
+    type Storage = DenseVecStorage<Self>;
+}
+
+// Screen label following an entity of the scene
+pub struct NodeLabel {
+    pub node: Entity,
+    // Font size at the reference distance
+    pub size: f32,
+    // Wood titles win over node labels when they overlap
+    pub title: bool,
+}
+
+impl Component for NodeLabel {
+    type Storage = DenseVecStorage<Self>;
+}
@@ -0,0 +1,561 @@
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use crate::misc::*;
+use crate::input::{self, InputFormat};
+use log::info;
+
+// How a wood document is turned into nodes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParseMode {
+    // Objects shaped like `{ "name", "children": { ... } }`
+    Children,
+    // Any json value, objects and arrays become inner nodes, scalars become leaves
+    Generic,
+}
+
+impl ParseMode {
+    pub fn parse(mode: &str) -> Option<ParseMode> {
+        match mode {
+            "children" => Some(ParseMode::Children),
+            "json" | "generic" => Some(ParseMode::Generic),
+            _ => None,
+        }
+    }
+}
+
+// Key names read from the tree document in children mode
+#[derive(Clone)]
+pub struct KeyNames {
+    pub name: String,
+    pub display_name: String,
+    pub children: String,
+    // List of node paths linked as extra children
+    pub links: String,
+}
+
+impl KeyNames {
+    pub fn new() -> KeyNames {
+        KeyNames {
+            name: "name".to_string(),
+            display_name: "display_name".to_string(),
+            children: "children".to_string(),
+            links: "links".to_string(),
+        }
+    }
+
+    pub fn parse(raw: &Value) -> KeyNames {
+        let keys = KeyNames::new();
+        KeyNames {
+            name: raw.get_string("name", keys.name),
+            display_name: raw.get_string("display_name", keys.display_name),
+            children: raw.get_string("children", keys.children),
+            links: raw.get_string("links", keys.links),
+        }
+    }
+
+    // Read the name of an array form child, strings and integers are accepted
+    pub fn read_name(&self, raw: &Value) -> Option<String> {
+        let name = &raw[self.name.as_str()];
+        if let Some(name) = name.as_str() {
+            if !name.is_empty() {
+                return Some(name.to_string());
+            }
+        } else if let Some(name) = name.as_i64() {
+            return Some(name.to_string());
+        }
+        None
+    }
+}
+
+impl Default for KeyNames {
+    fn default() -> Self {
+        KeyNames::new()
+    }
+}
+
+// Sibling ordering
+#[derive(Clone, PartialEq, Debug)]
+pub enum SortMode {
+    // Keep the order of the source document
+    Source,
+    Name,
+    // Number of descendants
+    Size,
+    // Numeric attribute of the node, nodes without it go last
+    Attribute(String),
+}
+
+#[derive(Clone)]
+pub struct SortConfig {
+    pub mode: SortMode,
+    pub reverse: bool,
+}
+
+impl SortConfig {
+    pub fn new() -> SortConfig {
+        SortConfig {
+            mode: SortMode::Source,
+            reverse: false,
+        }
+    }
+
+    // Accepts either "source" | "name" | "size" or
+    // { "by": "source" | "name" | "size" | "attribute", "key": "cost", "reverse": true }
+    pub fn parse(raw: &Value) -> Result<SortConfig, ParseError> {
+        let mut config = SortConfig::new();
+        let by = match raw {
+            Value::Null => return Ok(config),
+            Value::String(by) => by.clone(),
+            Value::Object(_) => {
+                config.reverse = raw.get_bool("reverse", false);
+                raw.get_str("by", "source")
+            },
+            _ => return Err(ParseError::new(format!("Invalid sort {}", raw))),
+        };
+        config.mode = match by.as_str() {
+            "source" => SortMode::Source,
+            "name" => SortMode::Name,
+            "size" => SortMode::Size,
+            "attribute" => match raw["key"].as_str() {
+                Some(key) => SortMode::Attribute(key.to_string()),
+                None => return Err(ParseError::new("Sorting by attribute requires a \"key\"".to_string())),
+            },
+            _ => return Err(ParseError::new(format!("Unknown sort mode {}", by))),
+        };
+        Ok(config)
+    }
+}
+
+impl Default for SortConfig {
+    fn default() -> Self {
+        SortConfig::new()
+    }
+}
+
+// Per wood settings
+//
+// A wood entry is either the tree document itself, or a settings object
+// carrying the document under `tree`:
+//
+// {
+//   "name": "api",
+//   "title": "Public API",
+//   "offset": [0, 0, 30],
+//   "mode": "json",
+//   "keys": { "name": "id", "display_name": "title", "children": "nodes", "links": "depends_on" },
+//   "sort": { "by": "attribute", "key": "cost", "reverse": true },
+//   "tree": { ... }
+// }
+#[derive(Clone)]
+pub struct WoodConfig {
+    pub name: Option<String>,
+    // Floating label of the wood, defaults to the name of the root
+    pub title: Option<String>,
+    // Added to the position given by the forest arrangement
+    pub offset: (f32, f32, f32),
+    pub mode: ParseMode,
+    pub keys: KeyNames,
+    pub sort: SortConfig,
+}
+
+impl WoodConfig {
+    pub fn new() -> WoodConfig {
+        WoodConfig {
+            name: None,
+            title: None,
+            offset: (0.0, 0.0, 0.0),
+            mode: ParseMode::Children,
+            keys: KeyNames::new(),
+            sort: SortConfig::new(),
+        }
+    }
+
+    // Keys a settings object may hold, a document with a `tree` among other keys is data
+    pub const SETTINGS_KEYS: &'static [&'static str] = &["name", "title", "offset", "mode", "keys", "sort", "tree", "file", "format"];
+
+    pub fn is_settings(raw: &Value) -> bool {
+        match raw.as_object() {
+            Some(fields) => fields.contains_key("tree") && fields.keys().all(|key| WoodConfig::SETTINGS_KEYS.contains(&key.as_str())),
+            None => false,
+        }
+    }
+
+    // Split a wood entry into its settings and the tree document
+    pub fn parse(raw: &Value) -> Result<(WoodConfig, &Value), ParseError> {
+        let mut config = WoodConfig::new();
+        if !WoodConfig::is_settings(raw) {
+            return Ok((config, raw));
+        }
+
+        if let Some(name) = raw["name"].as_str() {
+            config.name = Some(name.to_string());
+        }
+        if let Some(title) = raw["title"].as_str() {
+            config.title = Some(title.to_string());
+        }
+        if let Some(offset) = raw["offset"].as_array() {
+            let axis = |i: usize| offset.get(i).and_then(|v| v.as_f64()).unwrap_or(0.0) as f32;
+            config.offset = (axis(0), axis(1), axis(2));
+        }
+
+        let mode = raw.get_str("mode", "children");
+        match ParseMode::parse(&mode) {
+            Some(mode) => config.mode = mode,
+            None => return Err(ParseError::new(format!("Unknown parse mode {}", mode))),
+        }
+        config.keys = KeyNames::parse(&raw["keys"]);
+        config.sort = SortConfig::parse(&raw["sort"])?;
+        Ok((config, &raw["tree"]))
+    }
+}
+
+impl Default for WoodConfig {
+    fn default() -> Self {
+        WoodConfig::new()
+    }
+}
+
+// How woods are placed next to each other
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArrangementKind {
+    Grid,
+    Circle,
+}
+
+#[derive(Clone)]
+pub struct Arrangement {
+    pub kind: ArrangementKind,
+    // Grid columns, defaults to a square grid
+    pub columns: Option<usize>,
+    // Distance between woods, defaults to the widest wood footprint
+    pub spacing: Option<f32>,
+}
+
+impl Arrangement {
+    pub fn new() -> Arrangement {
+        Arrangement {
+            kind: ArrangementKind::Grid,
+            columns: None,
+            spacing: None,
+        }
+    }
+
+    // { "kind": "grid" | "circle", "columns": 3, "spacing": 80 }
+    pub fn parse(raw: &Value) -> Result<Arrangement, ParseError> {
+        let mut arrangement = Arrangement::new();
+        let kind = raw.get_str("kind", "grid");
+        arrangement.kind = match kind.as_str() {
+            "grid" => ArrangementKind::Grid,
+            "circle" => ArrangementKind::Circle,
+            _ => return Err(ParseError::new(format!("Unknown arrangement {}", kind))),
+        };
+        arrangement.columns = raw["columns"].as_u64().map(|v| v.max(1) as usize);
+        arrangement.spacing = raw["spacing"].as_f64().map(|v| v as f32);
+        Ok(arrangement)
+    }
+}
+
+impl Default for Arrangement {
+    fn default() -> Self {
+        Arrangement::new()
+    }
+}
+
+// Node placement algorithm of the scene
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LayoutKind {
+    // Children on a circle below their parent
+    Cone,
+    // Flat tidy tree, seen through an orthographic camera
+    Tidy,
+    // Depth as distance from the root, subtrees get angles by weight
+    Radial,
+    // Nested rings of sectors, subtrees get angles by weight
+    Sunburst,
+    // Starts as a cone, then springs and repulsion untangle it in the viewer
+    Force,
+    // Nested rectangles with areas by weight
+    Treemap,
+    // Nested discs with areas by weight
+    Pack,
+}
+
+impl LayoutKind {
+    pub const NAMES: &'static [&'static str] = &["cone", "tidy", "radial", "sunburst", "force", "treemap", "pack"];
+    pub const ALL: &'static [LayoutKind] = &[
+        LayoutKind::Cone, LayoutKind::Tidy, LayoutKind::Radial, LayoutKind::Sunburst,
+        LayoutKind::Force, LayoutKind::Treemap, LayoutKind::Pack,
+    ];
+
+    pub fn parse(layout: &str) -> Option<LayoutKind> {
+        match layout {
+            "cone" => Some(LayoutKind::Cone),
+            "tidy" => Some(LayoutKind::Tidy),
+            "radial" => Some(LayoutKind::Radial),
+            "sunburst" => Some(LayoutKind::Sunburst),
+            "force" => Some(LayoutKind::Force),
+            "treemap" => Some(LayoutKind::Treemap),
+            "pack" => Some(LayoutKind::Pack),
+            _ => None,
+        }
+    }
+
+    // The layout after this one, wrapping around
+    pub fn next(&self) -> LayoutKind {
+        let i = LayoutKind::ALL.iter().position(|kind| kind == self).unwrap_or(0);
+        LayoutKind::ALL[(i + 1) % LayoutKind::ALL.len()]
+    }
+
+    // Flat layouts lie in the x-y plane
+    pub fn is_flat(&self) -> bool {
+        *self != LayoutKind::Cone && *self != LayoutKind::Force
+    }
+}
+
+// Direction flat layouts grow from the root
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Orientation {
+    TopDown,
+    LeftRight,
+    BottomUp,
+}
+
+impl Orientation {
+    pub const NAMES: &'static [&'static str] = &["top-down", "left-right", "bottom-up"];
+
+    pub fn parse(orientation: &str) -> Option<Orientation> {
+        match orientation {
+            "top-down" => Some(Orientation::TopDown),
+            "left-right" => Some(Orientation::LeftRight),
+            "bottom-up" => Some(Orientation::BottomUp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LayoutConfig {
+    pub kind: LayoutKind,
+    // Minimum gap between the footprints of sibling subtrees, padding inside treemap and pack parents
+    pub spacing: f32,
+    pub orientation: Orientation,
+    // Numeric attribute weighting the leaves of radial, treemap and pack layouts, leaves count 1 each without it
+    pub weight: Option<String>,
+    // Keep each depth of the force layout on its own plane
+    pub levels: bool,
+}
+
+impl LayoutConfig {
+    pub fn new() -> LayoutConfig {
+        LayoutConfig {
+            kind: LayoutKind::Cone,
+            spacing: 1.0,
+            orientation: Orientation::TopDown,
+            weight: None,
+            levels: false,
+        }
+    }
+
+    // Accepts either "cone" | "tidy" | "radial" | "sunburst" | "force" | "treemap" | "pack" or
+    // { "kind": "tidy", "spacing": 2, "orientation": "left-right", "weight": "cost", "levels": true }
+    pub fn parse(raw: &Value) -> Result<LayoutConfig, ParseError> {
+        let mut config = LayoutConfig::new();
+        let kind = match raw {
+            Value::Null => return Ok(config),
+            Value::String(kind) => kind.clone(),
+            Value::Object(_) => {
+                if let Some(spacing) = raw["spacing"].as_f64() {
+                    config.spacing = spacing.max(0.0) as f32;
+                }
+                let orientation = raw.get_str("orientation", "top-down");
+                match Orientation::parse(&orientation) {
+                    Some(orientation) => config.orientation = orientation,
+                    None => return Err(ParseError::new(format!("Unknown orientation {}", orientation))),
+                }
+                config.weight = raw["weight"].as_str().map(|key| key.to_string());
+                config.levels = raw.get_bool("levels", false);
+                raw.get_str("kind", "cone")
+            },
+            _ => return Err(ParseError::new(format!("Invalid layout {}", raw))),
+        };
+        match LayoutKind::parse(&kind) {
+            Some(kind) => config.kind = kind,
+            None => return Err(ParseError::new(format!("Unknown layout {}", kind))),
+        }
+        Ok(config)
+    }
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig::new()
+    }
+}
+
+// Top level configuration
+//
+// Either a single wood entry, or a list of them:
+//
+// {
+//   "woods": [
+//     { "file": "services.json" },
+//     { "name": "api", "mode": "json", "file": "response.json", "offset": [0, 20, 0] },
+//     { "file": "topology.yaml" },
+//     { "file": "services.conf", "format": "toml" }
+//   ],
+//   "arrangement": { "kind": "circle" },
+//   "layout": { "kind": "cone", "spacing": 2 }
+// }
+#[derive(Clone)]
+pub struct ForestConfig {
+    pub woods: Vec<Value>,
+    pub arrangement: Arrangement,
+    pub layout: LayoutConfig,
+    // Files the woods were read from
+    pub files: Vec<PathBuf>,
+    // Stem of the file of each wood, names the root when the document does not
+    pub stems: Vec<Option<String>>,
+}
+
+impl ForestConfig {
+    pub fn new() -> ForestConfig {
+        ForestConfig {
+            woods: Vec::new(),
+            arrangement: Arrangement::new(),
+            layout: LayoutConfig::new(),
+            files: Vec::new(),
+            stems: Vec::new(),
+        }
+    }
+
+    fn stem(path: &Path) -> Option<String> {
+        path.file_stem().map(|stem| stem.to_string_lossy().to_string())
+    }
+
+    // Files are resolved relative to base
+    pub fn parse(raw: &Value, base: &Path) -> Result<ForestConfig, ParseError> {
+        let mut config = ForestConfig::new();
+        match raw["woods"].as_array() {
+            Some(woods) => {
+                for wood in woods.iter() {
+                    config.woods.push(ForestConfig::load_wood(wood, base)?);
+                    config.stems.push(wood["file"].as_str().and_then(|file| ForestConfig::stem(Path::new(file))));
+                    if let Some(file) = wood["file"].as_str() {
+                        config.files.push(base.join(file));
+                    }
+                }
+                config.arrangement = Arrangement::parse(&raw["arrangement"])?;
+                config.layout = LayoutConfig::parse(&raw["layout"])?;
+            },
+            None => {
+                config.woods.push(ForestConfig::load_wood(raw, base)?);
+                config.stems.push(raw["file"].as_str().and_then(|file| ForestConfig::stem(Path::new(file))));
+                if let Some(file) = raw["file"].as_str() {
+                    config.files.push(base.join(file));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    // Replace the `file` of a wood entry with its content under `tree`,
+    // the file format is detected from its extension unless given as `format`
+    pub fn load_wood(raw: &Value, base: &Path) -> Result<Value, ParseError> {
+        let file = match raw["file"].as_str() {
+            Some(file) => file,
+            None => return Ok(raw.clone()),
+        };
+        let format = match raw["format"].as_str() {
+            Some(format) => match InputFormat::parse(format) {
+                Some(format) => Some(format),
+                None => return Err(ParseError::new(format!("Unknown input format {}", format))),
+            },
+            None => None,
+        };
+        let tree = input::read_file(&base.join(file), format)?;
+        let mut wood = raw.clone();
+        if let Some(entry) = wood.as_object_mut() {
+            entry.remove("file");
+            entry.remove("format");
+            entry.insert("tree".to_string(), tree);
+        }
+        Ok(wood)
+    }
+
+    // Add a tree file as a wood, `-` reads stdin
+    pub fn add_file(&mut self, path: &Path, format: Option<InputFormat>) -> Result<(), ParseError> {
+        let tree = if path == Path::new("-") {
+            input::read_stdin(format)?
+        } else {
+            self.files.push(path.to_path_buf());
+            input::read_file(path, format)?
+        };
+        self.woods.push(tree);
+        self.stems.push(if path == Path::new("-") { None } else { ForestConfig::stem(path) });
+        Ok(())
+    }
+}
+
+impl Default for ForestConfig {
+    fn default() -> Self {
+        ForestConfig::new()
+    }
+}
+
+// Where the forest is read from, tree files take precedence over the config file
+#[derive(Clone)]
+pub struct ForestSource {
+    pub files: Vec<PathBuf>,
+    pub config: PathBuf,
+    pub format: Option<InputFormat>,
+    // Command line overrides of the configured layout
+    pub layout: Option<LayoutKind>,
+    pub orientation: Option<Orientation>,
+}
+
+impl ForestSource {
+    pub fn read(&self) -> Result<ForestConfig, ParseError> {
+        let mut forest = if !self.files.is_empty() {
+            let mut forest = ForestConfig::new();
+            for file in self.files.iter() {
+                forest.add_file(file, self.format)?;
+            }
+            forest
+        } else {
+            info!("Loading configuration from {}", self.config.display());
+            let raw = input::read_file(&self.config, None)?;
+            let base = self.config.parent().unwrap_or(Path::new("."));
+            let mut forest = ForestConfig::parse(&raw, base)?;
+            forest.files.push(self.config.clone());
+            forest
+        };
+
+        if let Some(kind) = self.layout {
+            forest.layout.kind = kind;
+        }
+        if let Some(orientation) = self.orientation {
+            forest.layout.orientation = orientation;
+        }
+        Ok(forest)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn settings_hold_only_known_keys() {
+        assert!(WoodConfig::is_settings(&json!({ "tree": {} })));
+        assert!(WoodConfig::is_settings(&json!({ "name": "api", "mode": "json", "offset": [0, 1, 0], "tree": [] })));
+        // Data which happens to hold a tree field is parsed whole
+        let data = json!({ "name": "forest", "tree": { "oak": 3 }, "area": 12 });
+        assert!(!WoodConfig::is_settings(&data));
+        let (config, tree) = WoodConfig::parse(&data).unwrap();
+        assert!(config.name.is_none());
+        assert_eq!(tree, &data);
+        assert!(!WoodConfig::is_settings(&json!(["tree"])));
+    }
+}
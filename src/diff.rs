@@ -0,0 +1,505 @@
+use crate::node::*;
+use crate::tree::*;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DiffStatus {
+    Unchanged,
+    // Same path, different attributes
+    Changed,
+    // Same name in the same wood, under another path
+    Moved,
+    Added,
+    Removed,
+}
+
+impl DiffStatus {
+    pub const ALL: [DiffStatus; 5] = [
+        DiffStatus::Unchanged,
+        DiffStatus::Changed,
+        DiffStatus::Moved,
+        DiffStatus::Added,
+        DiffStatus::Removed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiffStatus::Unchanged => "unchanged",
+            DiffStatus::Changed => "changed",
+            DiffStatus::Moved => "moved",
+            DiffStatus::Added => "added",
+            DiffStatus::Removed => "removed",
+        }
+    }
+
+    // Color of the nodes of the merged wood
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            DiffStatus::Unchanged => [0.6, 0.6, 0.6, 1.0],
+            DiffStatus::Changed => [0.95, 0.75, 0.1, 1.0],
+            DiffStatus::Moved => [0.3, 0.5, 0.95, 1.0],
+            DiffStatus::Added => [0.2, 0.8, 0.3, 1.0],
+            DiffStatus::Removed => [0.9, 0.2, 0.2, 1.0],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DiffFormat {
+    // One line per differing node, then a summary
+    Text,
+    Json,
+    // Merged wood colored by status
+    Svg,
+}
+
+impl DiffFormat {
+    pub const NAMES: &'static [&'static str] = &["text", "json", "svg"];
+
+    pub fn parse(format: &str) -> Option<DiffFormat> {
+        match format {
+            "text" => Some(DiffFormat::Text),
+            "json" => Some(DiffFormat::Json),
+            "svg" => Some(DiffFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+// Attribute whose value differs between both versions, None when missing from one of them
+pub struct AttributeChange {
+    pub key: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+pub struct DiffEntry {
+    // Path in the new version, in the old one for removed nodes
+    pub path: String,
+    pub status: DiffStatus,
+    // Path in the old version of moved nodes
+    pub from: Option<String>,
+    pub changes: Vec<AttributeChange>,
+}
+
+pub struct Diff {
+    // Every node of both versions, by path
+    pub entries: Vec<DiffEntry>,
+    // Both versions in a single forest, removed nodes stay under their old parent
+    pub merged: Forest,
+    // Status of every node of the merged forest, by merged path
+    pub statuses: HashMap<String, DiffStatus>,
+}
+
+impl Diff {
+    pub fn count(&self, status: DiffStatus) -> usize {
+        self.entries.iter().filter(|entry| entry.status == status).count()
+    }
+}
+
+// Nodes of all woods by path
+fn nodes_by_path(woods: &Forest) -> HashMap<String, Arc<Node>> {
+    let mut nodes = HashMap::new();
+    for wood in woods.ordered().iter() {
+        let wood = wood.read().unwrap();
+        let by_depth = wood.wood.get_nodes_by_depths();
+        let by_depth = by_depth.read().unwrap();
+        for node in by_depth.values().flat_map(|items| items.iter()).filter_map(|item| item.upgrade()) {
+            let path = node.read().unwrap().read_path();
+            nodes.insert(path, node);
+        }
+    }
+    nodes
+}
+
+// Wood and name of a node, moved nodes keep both
+fn identity(path: &str) -> (Option<String>, String) {
+    (GodswoodMeta::parse_app_name(&path.to_string()), GodsnodePath::split_last(path).1.to_string())
+}
+
+fn attribute_changes(old: &NodeProto, new: &NodeProto) -> Vec<AttributeChange> {
+    let mut keys: Vec<&String> = old.attributes.keys().collect();
+    keys.extend(new.attributes.keys().filter(|key| !old.attributes.contains_key(*key)));
+    keys.into_iter().filter_map(|key| {
+        let (before, after) = (old.attributes.get(key), new.attributes.get(key));
+        if before == after {
+            None
+        } else {
+            Some(AttributeChange { key: key.clone(), old: before.cloned(), new: after.cloned() })
+        }
+    }).collect()
+}
+
+// Match the nodes of both versions by path. Nodes found in one version only are moved when
+// they keep their name and wood, either below a moved parent or when they are the only
+// added and removed node of that name, and added or removed otherwise.
+pub fn diff(old: &Forest, new: &Forest) -> Diff {
+    let old_nodes = nodes_by_path(old);
+    let new_nodes = nodes_by_path(new);
+
+    // Parents before children, so children of moved nodes follow them
+    let mut added: Vec<String> = new_nodes.keys().filter(|path| !old_nodes.contains_key(*path)).cloned().collect();
+    added.sort_by(|a, b| a.matches('.').count().cmp(&b.matches('.').count()).then(a.cmp(b)));
+    let mut removed: HashSet<String> = old_nodes.keys().filter(|path| !new_nodes.contains_key(*path)).cloned().collect();
+
+    let mut added_names: HashMap<(Option<String>, String), usize> = HashMap::new();
+    for path in added.iter() {
+        *added_names.entry(identity(path)).or_insert(0) += 1;
+    }
+    let mut removed_names: HashMap<(Option<String>, String), Vec<String>> = HashMap::new();
+    for path in removed.iter() {
+        removed_names.entry(identity(path)).or_insert_with(Vec::new).push(path.clone());
+    }
+
+    // New path to old path of moved nodes
+    let mut moved: HashMap<String, String> = HashMap::new();
+    for path in added.iter() {
+        let (parent, name) = GodsnodePath::split_last(path);
+        let below = parent
+            .and_then(|parent| moved.get(parent))
+            .map(|from| format!("{}.{}", from, name))
+            .filter(|from| removed.contains(from));
+        let from = below.or_else(|| {
+            let key = identity(path);
+            if added_names.get(&key) != Some(&1) {
+                return None;
+            }
+            let candidates: Vec<&String> = removed_names.get(&key)?.iter().filter(|from| removed.contains(*from)).collect();
+            if candidates.len() == 1 { Some(candidates[0].clone()) } else { None }
+        });
+        if let Some(from) = from {
+            removed.remove(&from);
+            moved.insert(path.clone(), from);
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (path, node) in new_nodes.iter() {
+        let from = moved.get(path).cloned();
+        let previous = from.as_ref().unwrap_or(path);
+        let changes = match old_nodes.get(previous) {
+            Some(before) => attribute_changes(&before.read().unwrap(), &node.read().unwrap()),
+            None => Vec::new(),
+        };
+        let status = if from.is_some() {
+            DiffStatus::Moved
+        } else if !old_nodes.contains_key(path) {
+            DiffStatus::Added
+        } else if !changes.is_empty() {
+            DiffStatus::Changed
+        } else {
+            DiffStatus::Unchanged
+        };
+        entries.push(DiffEntry { path: path.clone(), status, from, changes });
+    }
+    for path in removed.iter() {
+        entries.push(DiffEntry { path: path.clone(), status: DiffStatus::Removed, from: None, changes: Vec::new() });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let statuses: HashMap<String, DiffStatus> = entries.iter()
+        .filter(|entry| entry.status != DiffStatus::Removed)
+        .map(|entry| (entry.path.clone(), entry.status))
+        .collect();
+    let merger = Merger {
+        old_nodes: &old_nodes,
+        statuses: &statuses,
+        moved: &moved,
+        removed: &removed,
+        merged: Forest::new(),
+        merged_statuses: HashMap::new(),
+    };
+    let (merged, statuses) = merger.merge(old, new);
+    Diff { entries, merged, statuses }
+}
+
+// Builds the merged forest out of copies of the nodes of both versions
+struct Merger<'a> {
+    old_nodes: &'a HashMap<String, Arc<Node>>,
+    statuses: &'a HashMap<String, DiffStatus>,
+    moved: &'a HashMap<String, String>,
+    removed: &'a HashSet<String>,
+    merged: Forest,
+    merged_statuses: HashMap<String, DiffStatus>,
+}
+
+impl<'a> Merger<'a> {
+    // Woods of the new version, then the woods removed from the old one
+    fn merge(mut self, old: &Forest, new: &Forest) -> (Forest, HashMap<String, DiffStatus>) {
+        let mut roots = Vec::new();
+        for wood in new.ordered().iter() {
+            if let Some(root) = wood.read().unwrap().wood.get_root().upgrade() {
+                roots.push(self.merge_new(&root, ""));
+            }
+        }
+        for wood in old.ordered().iter() {
+            if let Some(root) = wood.read().unwrap().wood.get_root().upgrade() {
+                if self.removed.contains(&root.read().unwrap().read_path()) {
+                    roots.push(self.merge_removed(&root, ""));
+                }
+            }
+        }
+        for root in roots.iter() {
+            // Woods of the new version have unique names and removed woods are missing from it
+            self.merged.add_tree(root).unwrap();
+        }
+        (self.merged, self.merged_statuses)
+    }
+
+    // Copy of a node placed under the merged node with the given path
+    fn copy(&mut self, source: &Arc<Node>, status: DiffStatus, parent: &str) -> (Arc<Node>, String) {
+        let copy = self.merged.store.new_node();
+        let path = {
+            let source = source.read().unwrap();
+            let mut state = copy.write().unwrap();
+            state.name = source.name.clone();
+            state.display_name = source.display_name.clone();
+            state.value_type = source.value_type;
+            state.attributes = source.attributes.clone();
+            format!("{}.{}", parent, GodsnodePath::escape(&source.name))
+        };
+        self.merged_statuses.insert(path.clone(), status);
+        (copy, path)
+    }
+
+    fn link(parent: &Arc<Node>, child: &Arc<Node>) {
+        parent.write().unwrap().add_child(Arc::downgrade(child));
+        child.write().unwrap().add_parent(Arc::downgrade(parent));
+    }
+
+    // Node of the new version with its children, and the removed children of its old version
+    fn merge_new(&mut self, node: &Arc<Node>, parent: &str) -> Arc<Node> {
+        let path = node.read().unwrap().read_path();
+        let status = self.statuses.get(&path).cloned().unwrap_or(DiffStatus::Unchanged);
+        let (copy, merged_path) = self.copy(node, status, parent);
+
+        let children: Vec<Arc<Node>> = node.read().unwrap().children.iter().filter_map(|kid| kid.upgrade()).collect();
+        for child in children.iter() {
+            let child = self.merge_new(child, &merged_path);
+            Merger::link(&copy, &child);
+        }
+
+        let previous = self.moved.get(&path).unwrap_or(&path);
+        if let Some(before) = self.old_nodes.get(previous).cloned() {
+            self.merge_removed_children(&copy, &merged_path, &before);
+        }
+        copy
+    }
+
+    fn merge_removed(&mut self, node: &Arc<Node>, parent: &str) -> Arc<Node> {
+        let (copy, merged_path) = self.copy(node, DiffStatus::Removed, parent);
+        self.merge_removed_children(&copy, &merged_path, node);
+        copy
+    }
+
+    // Children of an old node which are gone, not moved elsewhere
+    fn merge_removed_children(&mut self, copy: &Arc<Node>, merged_path: &str, before: &Arc<Node>) {
+        let children: Vec<Arc<Node>> = before.read().unwrap().children.iter().filter_map(|kid| kid.upgrade()).collect();
+        for child in children.iter() {
+            if self.removed.contains(&child.read().unwrap().read_path()) {
+                let child = self.merge_removed(child, merged_path);
+                Merger::link(copy, &child);
+            }
+        }
+    }
+}
+
+fn show(value: &Option<Value>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+// +, -, > and ~ lines for added, removed, moved and changed nodes, then the counts
+pub fn report_text(diff: &Diff) -> String {
+    let mut lines = Vec::new();
+    for entry in diff.entries.iter() {
+        match entry.status {
+            DiffStatus::Unchanged => continue,
+            DiffStatus::Added => lines.push(format!("+ {}", entry.path)),
+            DiffStatus::Removed => lines.push(format!("- {}", entry.path)),
+            DiffStatus::Moved => lines.push(format!("> {} (from {})", entry.path, entry.from.clone().unwrap_or_default())),
+            DiffStatus::Changed => lines.push(format!("~ {}", entry.path)),
+        }
+        for change in entry.changes.iter() {
+            lines.push(format!("    {}: {} -> {}", change.key, show(&change.old), show(&change.new)));
+        }
+    }
+    let counts: Vec<String> = [DiffStatus::Added, DiffStatus::Removed, DiffStatus::Moved, DiffStatus::Changed, DiffStatus::Unchanged]
+        .iter()
+        .map(|status| format!("{} {}", diff.count(*status), status.name()))
+        .collect();
+    lines.push(counts.join(", "));
+    lines.join("\n") + "\n"
+}
+
+// Counts by status and the nodes which differ
+pub fn report_json(diff: &Diff) -> Value {
+    let mut summary = serde_json::Map::new();
+    for status in DiffStatus::ALL.iter() {
+        summary.insert(status.name().to_string(), json!(diff.count(*status)));
+    }
+    let nodes: Vec<Value> = diff.entries.iter()
+        .filter(|entry| entry.status != DiffStatus::Unchanged)
+        .map(|entry| {
+            let changes: Vec<Value> = entry.changes.iter()
+                .map(|change| json!({ "key": change.key, "old": change.old, "new": change.new }))
+                .collect();
+            let mut raw = json!({ "path": entry.path, "status": entry.status.name() });
+            if let Some(from) = &entry.from {
+                raw["from"] = json!(from);
+            }
+            if !changes.is_empty() {
+                raw["changes"] = Value::Array(changes);
+            }
+            raw
+        })
+        .collect();
+    json!({ "summary": summary, "nodes": nodes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ForestConfig;
+
+    fn forest(trees: Vec<Value>) -> Forest {
+        let mut config = ForestConfig::new();
+        config.woods = trees;
+        Forest::load(&config).unwrap()
+    }
+
+    fn entry<'a>(diff: &'a Diff, path: &str) -> &'a DiffEntry {
+        diff.entries.iter().find(|entry| entry.path == path).unwrap_or_else(|| panic!("no entry for {}", path))
+    }
+
+    // Sorted names of the children of a node of the merged forest
+    fn merged_children(diff: &Diff, path: &str) -> Vec<String> {
+        let node = diff.merged.store.get_weak_node(&path.to_string()).and_then(|node| node.upgrade()).unwrap();
+        let node = node.read().unwrap();
+        let mut names: Vec<String> = node.children.iter().filter_map(|kid| kid.upgrade()).map(|kid| kid.read().unwrap().name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn statuses_by_path() {
+        let old = forest(vec![json!({ "name": "app", "children": { "a": { "cost": 1 }, "b": {}, "c": {} } })]);
+        let new = forest(vec![json!({ "name": "app", "children": { "a": { "cost": 2 }, "b": {}, "d": {} } })]);
+        let diff = diff(&old, &new);
+
+        assert_eq!(entry(&diff, ".app").status, DiffStatus::Unchanged);
+        assert_eq!(entry(&diff, ".app.b").status, DiffStatus::Unchanged);
+        assert_eq!(entry(&diff, ".app.d").status, DiffStatus::Added);
+        assert_eq!(entry(&diff, ".app.c").status, DiffStatus::Removed);
+        let changed = entry(&diff, ".app.a");
+        assert_eq!(changed.status, DiffStatus::Changed);
+        assert_eq!(changed.changes.len(), 1);
+        assert_eq!(changed.changes[0].key, "cost");
+        assert_eq!((changed.changes[0].old.clone(), changed.changes[0].new.clone()), (Some(json!(1)), Some(json!(2))));
+        assert_eq!(diff.entries.len(), 5);
+    }
+
+    #[test]
+    fn unique_names_move() {
+        let old = forest(vec![json!({ "name": "app", "children": { "x": { "children": { "leaf": {} } }, "y": {} } })]);
+        let new = forest(vec![json!({ "name": "app", "children": { "x": {}, "y": { "children": { "leaf": {} } } } })]);
+        let diff = diff(&old, &new);
+
+        let moved = entry(&diff, ".app.y.leaf");
+        assert_eq!(moved.status, DiffStatus::Moved);
+        assert_eq!(moved.from.as_deref(), Some(".app.x.leaf"));
+        assert!(diff.entries.iter().all(|entry| entry.path != ".app.x.leaf"));
+        assert_eq!(diff.count(DiffStatus::Removed), 0);
+    }
+
+    #[test]
+    fn ambiguous_names_do_not_move() {
+        let old = forest(vec![json!({ "name": "app", "children": {
+            "x": { "children": { "leaf": {} } },
+            "y": { "children": { "leaf": {} } },
+        } })]);
+        let new = forest(vec![json!({ "name": "app", "children": { "x": {}, "y": {}, "z": { "children": { "leaf": {} } } } })]);
+        let diff = diff(&old, &new);
+
+        assert_eq!(entry(&diff, ".app.z.leaf").status, DiffStatus::Added);
+        assert_eq!(entry(&diff, ".app.x.leaf").status, DiffStatus::Removed);
+        assert_eq!(entry(&diff, ".app.y.leaf").status, DiffStatus::Removed);
+        assert_eq!(diff.count(DiffStatus::Moved), 0);
+
+        // Two new nodes of the same name for a single removed one
+        let old = forest(vec![json!({ "name": "app", "children": { "x": { "children": { "leaf": {} } }, "y": {}, "z": {} } })]);
+        let new = forest(vec![json!({ "name": "app", "children": {
+            "x": {},
+            "y": { "children": { "leaf": {} } },
+            "z": { "children": { "leaf": {} } },
+        } })]);
+        let diff = super::diff(&old, &new);
+
+        assert_eq!(entry(&diff, ".app.x.leaf").status, DiffStatus::Removed);
+        assert_eq!(diff.count(DiffStatus::Added), 2);
+        assert_eq!(diff.count(DiffStatus::Moved), 0);
+    }
+
+    #[test]
+    fn children_follow_a_moved_parent() {
+        let old = forest(vec![json!({ "name": "app", "children": {
+            "x": { "children": { "group": { "children": { "one": {}, "gone": {} } } } },
+            "y": {},
+        } })]);
+        // A second new node named one keeps the moved child from matching by its name alone
+        let new = forest(vec![json!({ "name": "app", "children": {
+            "x": {},
+            "y": { "children": { "group": { "children": { "one": {} } } } },
+            "z": { "children": { "one": {} } },
+        } })]);
+        let diff = diff(&old, &new);
+
+        assert_eq!(entry(&diff, ".app.y.group").from.as_deref(), Some(".app.x.group"));
+        let child = entry(&diff, ".app.y.group.one");
+        assert_eq!(child.status, DiffStatus::Moved);
+        assert_eq!(child.from.as_deref(), Some(".app.x.group.one"));
+        assert_eq!(entry(&diff, ".app.z.one").status, DiffStatus::Added);
+        assert_eq!(entry(&diff, ".app.x.group.gone").status, DiffStatus::Removed);
+
+        // The removed child is merged under the new place of its parent
+        assert_eq!(merged_children(&diff, ".app.y.group"), vec!["gone", "one"]);
+        assert_eq!(diff.statuses.get(".app.y.group.gone"), Some(&DiffStatus::Removed));
+        assert_eq!(diff.statuses.get(".app.y.group.one"), Some(&DiffStatus::Moved));
+        assert!(!diff.statuses.contains_key(".app.x.group.gone"));
+    }
+
+    #[test]
+    fn merged_forest_holds_both_versions() {
+        let old = forest(vec![
+            json!({ "name": "app", "children": { "a": { "children": { "old": {} } }, "b": {} } }),
+            json!({ "name": "legacy", "children": { "c": {} } }),
+        ]);
+        let new = forest(vec![json!({ "name": "app", "children": { "a": { "children": { "new": {} } }, "b": { "size": 3 } } })]);
+        let diff = diff(&old, &new);
+
+        assert_eq!(diff.merged.names, vec!["app", "legacy"]);
+        assert_eq!(merged_children(&diff, ".app"), vec!["a", "b"]);
+        assert_eq!(merged_children(&diff, ".app.a"), vec!["new", "old"]);
+        assert_eq!(merged_children(&diff, ".legacy"), vec!["c"]);
+
+        // One status for every merged node, each under its merged path
+        let expected = [
+            (".app", DiffStatus::Unchanged),
+            (".app.a", DiffStatus::Unchanged),
+            (".app.a.new", DiffStatus::Added),
+            (".app.a.old", DiffStatus::Removed),
+            (".app.b", DiffStatus::Changed),
+            (".legacy", DiffStatus::Removed),
+            (".legacy.c", DiffStatus::Removed),
+        ];
+        assert_eq!(diff.statuses.len(), expected.len());
+        for (path, status) in expected.iter() {
+            assert_eq!(diff.statuses.get(*path), Some(status), "status of {}", path);
+            assert!(diff.merged.store.get_weak_node(&path.to_string()).is_some(), "no merged node {}", path);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::node::*;
+use crate::tree::*;
+use crate::config::LayoutConfig;
+use crate::diff::DiffStatus;
+use crate::layout::Layout;
+use crate::resource::Theme;
+use crate::svg::{self, Projection};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    // Children schema with array form children, loadable again
+    Json,
+    // One dotted node path per line
+    Paths,
+    // Projection of the scene layout
+    Svg,
+}
+
+impl ExportFormat {
+    pub const NAMES: &'static [&'static str] = &["json", "paths", "svg"];
+
+    pub fn parse(format: &str) -> Option<ExportFormat> {
+        match format {
+            "json" => Some(ExportFormat::Json),
+            "paths" => Some(ExportFormat::Paths),
+            "svg" => Some(ExportFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub layout: LayoutConfig,
+    // Camera and colors of the svg drawing
+    pub projection: Projection,
+    pub theme: Theme,
+    // Changes of the nodes of a diff by path, empty otherwise
+    pub statuses: HashMap<String, DiffStatus>,
+}
+
+pub fn export(woods: &Forest, options: &ExportOptions) -> String {
+    match options.format {
+        ExportFormat::Json => {
+            let raw = export_json(woods);
+            serde_json::to_string_pretty(&raw).unwrap_or_default() + "\n"
+        },
+        ExportFormat::Paths => export_paths(woods),
+        ExportFormat::Svg => {
+            // Same layout as the viewer, without a window
+            let placement = options.layout.engine().place(woods);
+            svg::render(&placement, &options.projection, &options.theme, &options.statuses)
+        },
+    }
+}
+
+// A single wood is exported as its tree, several woods as a forest config
+pub fn export_json(woods: &Forest) -> Value {
+    let mut trees: Vec<Value> = woods.ordered().iter().filter_map(|wood| {
+        let root = wood.read().unwrap().wood.get_root().upgrade()?;
+        Some(export_node(&root))
+    }).collect();
+
+    if trees.len() == 1 {
+        return trees.pop().unwrap();
+    }
+    let woods: Vec<Value> = trees.into_iter().map(|tree| json!({ "tree": tree })).collect();
+    json!({ "woods": woods })
+}
+
+fn export_node(node: &Arc<Node>) -> Value {
+    let node = node.read().unwrap();
+    let mut raw = node.attributes.clone();
+    raw.insert("name".to_string(), Value::String(node.name.clone()));
+    raw.insert("display_name".to_string(), Value::String(node.display_name.clone()));
+    let children: Vec<Value> = node.children.iter()
+        .filter_map(|kid| kid.upgrade())
+        .map(|kid| export_node(&kid))
+        .collect();
+    if !children.is_empty() {
+        raw.insert("children".to_string(), Value::Array(children));
+    }
+    Value::Object(raw)
+}
+
+pub fn export_paths(woods: &Forest) -> String {
+    let mut paths = String::new();
+    for wood in woods.ordered().iter() {
+        let wood = wood.read().unwrap();
+        let nodes = wood.wood.get_nodes_by_depths();
+        let nodes = nodes.read().unwrap();
+        for depth in 1..wood.wood.get_depth() + 1 {
+            for item in nodes.get(&depth).iter().flat_map(|items| items.iter()) {
+                if let Some(node) = item.upgrade() {
+                    paths.push_str(&node.read().unwrap().read_path());
+                    paths.push('\n');
+                }
+            }
+        }
+    }
+    paths
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use serde_json::{Number, Value};
+use crate::misc::*;
+
+// Documents of every format are converted to the json value tree consumed by the woods
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl InputFormat {
+    pub const NAMES: &'static [&'static str] = &["json", "yaml", "toml"];
+
+    pub fn parse(format: &str) -> Option<InputFormat> {
+        match format {
+            "json" => Some(InputFormat::Json),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            "toml" => Some(InputFormat::Toml),
+            _ => None,
+        }
+    }
+
+    // Detect the format from the file extension, json by default
+    pub fn detect(path: &Path) -> InputFormat {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| InputFormat::parse(&ext.to_lowercase()))
+            .unwrap_or(InputFormat::Json)
+    }
+
+    // Source names the document in error messages, the parsers of all formats
+    // report the line and column of the failure in their messages
+    pub fn parse_str(&self, text: &str, source: &str) -> Result<Value, ParseError> {
+        let failed = |e: &dyn std::fmt::Display| ParseError::new(format!("Failed to parse {}: {}", source, e));
+        match self {
+            InputFormat::Json => serde_json::from_str(text).map_err(|e| failed(&e)),
+            InputFormat::Yaml => {
+                let raw: serde_yaml::Value = serde_yaml::from_str(text).map_err(|e| failed(&e))?;
+                Ok(yaml_to_json(raw))
+            },
+            InputFormat::Toml => {
+                let raw: toml::Value = toml::from_str(text).map_err(|e| failed(&e))?;
+                Ok(toml_to_json(raw))
+            },
+        }
+    }
+}
+
+fn toml_to_json(raw: toml::Value) -> Value {
+    match raw {
+        toml::Value::String(v) => Value::String(v),
+        toml::Value::Integer(v) => Value::Number(v.into()),
+        toml::Value::Float(v) => Number::from_f64(v).map(Value::Number).unwrap_or(Value::Null),
+        toml::Value::Boolean(v) => Value::Bool(v),
+        toml::Value::Datetime(v) => Value::String(v.to_string()),
+        toml::Value::Array(list) => Value::Array(list.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
+    }
+}
+
+// Yaml mappings can have any key, scalar keys like `80:` or `true:` become strings
+fn yaml_to_json(raw: serde_yaml::Value) -> Value {
+    match raw {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(v) => Value::Bool(v),
+        serde_yaml::Value::Number(v) => {
+            if let Some(v) = v.as_i64() {
+                Value::Number(v.into())
+            } else if let Some(v) = v.as_u64() {
+                Value::Number(v.into())
+            } else {
+                v.as_f64().and_then(Number::from_f64).map(Value::Number).unwrap_or(Value::Null)
+            }
+        },
+        serde_yaml::Value::String(v) => Value::String(v),
+        serde_yaml::Value::Sequence(list) => Value::Array(list.into_iter().map(yaml_to_json).collect()),
+        serde_yaml::Value::Mapping(map) => Value::Object(map.into_iter().map(|(k, v)| (yaml_key(k), yaml_to_json(v))).collect()),
+    }
+}
+
+fn yaml_key(key: serde_yaml::Value) -> String {
+    match yaml_to_json(key) {
+        Value::String(key) => key,
+        key => key.to_string(),
+    }
+}
+
+// Read a document, the format is detected from the extension unless given
+pub fn read_file(path: &Path, format: Option<InputFormat>) -> Result<Value, ParseError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        ParseError::io(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    let format = format.unwrap_or_else(|| InputFormat::detect(path));
+    format.parse_str(&text, &path.display().to_string())
+}
+
+// Stdin is read as json unless told otherwise
+pub fn read_stdin(format: Option<InputFormat>) -> Result<Value, ParseError> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text).map_err(|e| {
+        ParseError::io(format!("Failed to read stdin: {}", e))
+    })?;
+    format.unwrap_or(InputFormat::Json).parse_str(&text, "stdin")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_are_detected_from_the_extension() {
+        assert_eq!(InputFormat::detect(Path::new("tree.json")), InputFormat::Json);
+        assert_eq!(InputFormat::detect(Path::new("tree.yaml")), InputFormat::Yaml);
+        assert_eq!(InputFormat::detect(Path::new("dir/tree.YML")), InputFormat::Yaml);
+        assert_eq!(InputFormat::detect(Path::new("Cargo.toml")), InputFormat::Toml);
+        // Anything else is read as json
+        assert_eq!(InputFormat::detect(Path::new("tree.txt")), InputFormat::Json);
+        assert_eq!(InputFormat::detect(Path::new("tree")), InputFormat::Json);
+    }
+
+    #[test]
+    fn yaml_becomes_json() {
+        let text = "name: app\nports:\n  80: http\n  443: https\nflags:\n  true: on\n  ~: none\nitems: [1, -2, 2.5, x]\n";
+        let raw = InputFormat::Yaml.parse_str(text, "app.yaml").unwrap();
+        assert_eq!(raw, serde_json::json!({
+            "name": "app",
+            "ports": { "80": "http", "443": "https" },
+            "flags": { "true": "on", "null": "none" },
+            "items": [1, -2, 2.5, "x"],
+        }));
+    }
+
+    #[test]
+    fn toml_becomes_json() {
+        let text = "name = \"app\"\nsize = 3\nratio = 0.5\n[children.a]\ntags = [\"x\", \"y\"]\n[children.b]\nenabled = true\n";
+        let raw = InputFormat::Toml.parse_str(text, "app.toml").unwrap();
+        assert_eq!(raw, serde_json::json!({
+            "name": "app",
+            "size": 3,
+            "ratio": 0.5,
+            "children": { "a": { "tags": ["x", "y"] }, "b": { "enabled": true } },
+        }));
+    }
+
+    #[test]
+    fn parse_errors_tell_where() {
+        let message = |format: InputFormat, text: &str| format.parse_str(text, "tree").err().unwrap().to_string();
+        let json = message(InputFormat::Json, "{\n  \"name\": \"app\",\n  \"children\": ]\n}");
+        assert!(json.starts_with("Failed to parse tree: ") && json.contains("line 3 column"), "{}", json);
+        let yaml = message(InputFormat::Yaml, "name: app\nchildren:\n  - a\n - b\n");
+        assert!(yaml.contains("line 4 column"), "{}", yaml);
+        let toml = message(InputFormat::Toml, "name = \"app\"\nsize = \n");
+        assert!(toml.contains("line 2 column"), "{}", toml);
+    }
+
+    #[test]
+    fn unreadable_files_are_io_errors() {
+        let e = read_file(Path::new("no/such/wood.json"), None).err().unwrap();
+        assert!(e.is_io());
+        assert!(e.to_string().starts_with("Failed to read no/such/wood.json"));
+        assert!(!InputFormat::Json.parse_str("{", "broken").err().unwrap().is_io());
+    }
+}
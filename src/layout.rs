@@ -0,0 +1,1183 @@
+use crate::node::*;
+use crate::tree::*;
+use crate::config::{LayoutConfig, LayoutKind, Orientation};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::PI;
+use std::sync::{Arc, RwLock};
+
+// Radius of the node spheres
+pub const NODE_RADIUS: f32 = 1.0;
+
+// Scene coordinates, y points up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Point {
+    pub fn new(x: f32, y: f32, z: f32) -> Point {
+        Point { x, y, z }
+    }
+}
+
+// Scene positions of the nodes of the forest, along with the lines to draw
+pub struct Placement {
+    // Nodes by path
+    pub nodes: Vec<(String, Arc<Node>, Point)>,
+    pub edges: Vec<(Point, Point)>,
+    pub rings: Vec<(Point, f32)>,
+    pub links: Vec<(Point, Point)>,
+    pub titles: Vec<(Point, String)>,
+    pub sectors: Vec<Sector>,
+    // Opposite corners of rectangles in the x-y plane
+    pub rects: Vec<(Point, Point)>,
+    // Circles in the x-y plane
+    pub discs: Vec<(Point, f32)>,
+}
+
+// Annular sector in the x-y plane, angles run clockwise from the top
+#[derive(Clone, Copy, Debug)]
+pub struct Sector {
+    pub center: Point,
+    pub inner: f32,
+    pub outer: f32,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Sector {
+    pub fn point(&self, radius: f32, angle: f32) -> Point {
+        polar(self.center, radius, angle)
+    }
+
+    // Outline as a closed polygon, arcs sampled about every segment radians
+    pub fn outline(&self, segment: f32) -> Vec<Point> {
+        let steps = ((self.end - self.start) / segment).ceil().max(1.0) as usize;
+        let angle = |i: usize| self.start + (self.end - self.start) * i as f32 / steps as f32;
+        let mut points: Vec<Point> = (0..steps + 1).map(|i| self.point(self.outer, angle(i))).collect();
+        points.extend((0..steps + 1).rev().map(|i| self.point(self.inner, angle(i))));
+        points.push(points[0]);
+        points
+    }
+}
+
+fn polar(center: Point, radius: f32, angle: f32) -> Point {
+    let angle = std::f32::consts::FRAC_PI_2 - angle;
+    Point::new(center.x + radius * angle.cos(), center.y + radius * angle.sin(), center.z)
+}
+
+impl Placement {
+    pub fn new() -> Placement {
+        Placement {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            rings: Vec::new(),
+            links: Vec::new(),
+            titles: Vec::new(),
+            sectors: Vec::new(),
+            rects: Vec::new(),
+            discs: Vec::new(),
+        }
+    }
+
+    // Box around everything placed, node spheres included
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let mut points: Vec<(Point, f32)> = Vec::new();
+        points.extend(self.nodes.iter().map(|(_, _, p)| (*p, NODE_RADIUS)));
+        points.extend(self.titles.iter().map(|(p, _)| (*p, NODE_RADIUS)));
+        points.extend(self.discs.iter().cloned());
+        points.extend(self.sectors.iter().map(|sector| (sector.center, sector.outer)));
+        for (a, b) in self.rects.iter() {
+            points.push((*a, 0.0));
+            points.push((*b, 0.0));
+        }
+        for (center, radius) in self.rings.iter() {
+            // Rings lie flat, they do not add height
+            points.push((Point::new(center.x - radius, center.y, center.z - radius), 0.0));
+            points.push((Point::new(center.x + radius, center.y, center.z + radius), 0.0));
+        }
+
+        let first = points.first()?.0;
+        let (mut min, mut max) = (first, first);
+        for (p, r) in points.iter() {
+            min = Point::new(min.x.min(p.x - r), min.y.min(p.y - r), min.z.min(p.z - r));
+            max = Point::new(max.x.max(p.x + r), max.y.max(p.y + r), max.z.max(p.z + r));
+        }
+        Some((min, max))
+    }
+
+    // Nodes are placed once at their tree position, links are drawn as cross-links between them
+    fn add_links(&mut self) {
+        let positions: HashMap<u64, Point> = self.nodes.iter()
+            .map(|(_, node, point)| (node.read().unwrap().id, *point))
+            .collect();
+        for (_, node, begin) in self.nodes.iter() {
+            for link in node.read().unwrap().get_links().iter() {
+                if let Some(target) = link.upgrade() {
+                    if let Some(end) = positions.get(&target.read().unwrap().id) {
+                        self.links.push((*begin, *end));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement::new()
+    }
+}
+
+// Turns woods into scene positions, without knowing how they get drawn
+pub trait Layout {
+    // Place the nodes of a single wood with its root at origin, returns where the title of the
+    // wood goes so sizes computed for the nodes are not computed again for it
+    fn place_wood(&self, wood: &Wood, origin: Point, placement: &mut Placement) -> Point;
+
+    // Size of a wood across the arrangement of the forest
+    fn extent(&self, wood: &Wood) -> f32;
+
+    fn place(&self, woods: &Forest) -> Placement {
+        let mut placement = Placement::new();
+        for wood in woods.ordered().iter() {
+            let wood = wood.read().unwrap();
+            let (x, y, z) = wood.origin;
+            let origin = Point::new(x, y, z);
+            let title = self.place_wood(&wood, origin, &mut placement);
+            placement.titles.push((title, wood.title.clone()));
+        }
+        placement.add_links();
+        placement
+    }
+}
+
+// Titles go above the root unless the layout needs that room
+fn above(wood: &Wood, origin: Point) -> Point {
+    Point::new(origin.x, origin.y + wood.base_gap, origin.z)
+}
+
+impl LayoutConfig {
+    pub fn engine(&self) -> Box<dyn Layout> {
+        match self.kind {
+            // The force layout is simulated in the viewer, starting from the cone
+            LayoutKind::Cone | LayoutKind::Force => Box::new(ConeLayout { spacing: self.spacing }),
+            LayoutKind::Tidy => Box::new(TidyLayout { spacing: self.spacing, orientation: self.orientation }),
+            LayoutKind::Radial => Box::new(RadialLayout { weight: self.weight.clone(), spacing: self.spacing, sunburst: false }),
+            LayoutKind::Sunburst => Box::new(RadialLayout { weight: self.weight.clone(), spacing: self.spacing, sunburst: true }),
+            LayoutKind::Treemap => Box::new(TreemapLayout { weight: self.weight.clone(), padding: self.spacing }),
+            LayoutKind::Pack => Box::new(PackLayout { weight: self.weight.clone(), padding: self.spacing }),
+        }
+    }
+}
+
+// Nodes of a wood at one depth, in tree order
+fn level(wood: &Wood, depth: usize) -> Vec<Arc<Node>> {
+    let nodes = wood.wood.get_nodes_by_depths();
+    let nodes = nodes.read().unwrap();
+    nodes.get(&depth).map(|items| items.iter().filter_map(|item| item.upgrade()).collect()).unwrap_or_default()
+}
+
+fn child_ids(node: &NodeProto) -> Vec<u64> {
+    node.get_children().iter().filter_map(|kid| kid.upgrade()).map(|kid| kid.read().unwrap().id).collect()
+}
+
+fn leaf_weight(node: &NodeProto, key: &Option<String>) -> f64 {
+    match key {
+        Some(key) => node.get_attribute_f64(key).unwrap_or(0.0).max(0.0),
+        None => 1.0,
+    }
+}
+
+// Weight of every node by id, summed bottom-up over the nodes by depth. Leaves weigh
+// the numeric attribute key, or 1 each without a key, other nodes the sum of their children.
+pub fn weights(wood: &Wood, key: &Option<String>) -> HashMap<u64, f64> {
+    let mut weights: HashMap<u64, f64> = HashMap::new();
+    for d in (1..wood.wood.get_depth() + 1).rev() {
+        for node in level(wood, d).iter() {
+            let node = node.read().unwrap();
+            let children = child_ids(&node);
+            let weight = if children.is_empty() {
+                leaf_weight(&node, key)
+            } else {
+                children.iter().map(|id| weights.get(id).cloned().unwrap_or(0.0)).sum()
+            };
+            weights.insert(node.id, weight);
+        }
+    }
+    weights
+}
+
+// Horizontal space taken by a subtree in the cone layout, mirrors the tree below the node
+pub struct Footprint {
+    // Radius of the disk around the node axis covering the whole subtree
+    pub radius: f32,
+    // Distance of the children from the node axis
+    pub ring: f32,
+    // Angle of each child around the node axis
+    pub angles: Vec<f32>,
+    pub children: Vec<Footprint>,
+}
+
+impl Footprint {
+    // Sized bottom-up, every child gets a wedge of the ring wide enough for its own footprint
+    // plus half the spacing on each side. Since a disk of radius r centered at distance R from
+    // the axis stays within the wedge of half angle asin(r / R), and wedges do not overlap,
+    // sibling footprints are always at least spacing apart.
+    pub fn cone<N: GodsnodeProto>(node: &Arc<RwLock<N>>, spacing: f32) -> Footprint {
+        let children: Vec<Footprint> = node.read().unwrap().visible_children().iter()
+            .filter_map(|child| child.upgrade())
+            .map(|child| Footprint::cone(&child, spacing))
+            .collect();
+
+        match children.len() {
+            0 => Footprint { radius: NODE_RADIUS, ring: 0.0, angles: Vec::new(), children },
+            1 => Footprint { radius: children[0].radius.max(NODE_RADIUS), ring: 0.0, angles: vec![0.0], children },
+            _ => {
+                let halves: Vec<f32> = children.iter().map(|child| child.radius + spacing / 2.0).collect();
+                let wedges = |ring: f32| -> Vec<f32> {
+                    halves.iter().map(|half| 2.0 * (half / ring).min(1.0).asin()).collect()
+                };
+                let fits = |ring: f32| wedges(ring).iter().sum::<f32>() <= 2.0 * PI;
+
+                // The ring can not be smaller than the widest child, grow it until every wedge fits
+                let mut low = halves.iter().cloned().fold(0f32, f32::max);
+                let mut ring = low;
+                if !fits(ring) {
+                    let mut high = low * 2.0;
+                    while !fits(high) {
+                        low = high;
+                        high *= 2.0;
+                    }
+                    for _ in 0..32 {
+                        let middle = (low + high) / 2.0;
+                        if fits(middle) {
+                            high = middle;
+                        } else {
+                            low = middle;
+                        }
+                    }
+                    ring = high;
+                }
+
+                // Spread what is left of the circle evenly between the wedges
+                let wedges = wedges(ring);
+                let slack = (2.0 * PI - wedges.iter().sum::<f32>()).max(0.0) / wedges.len() as f32;
+                let mut angles = vec![0f32];
+                for i in 1..wedges.len() {
+                    let angle = angles[i - 1] + (wedges[i - 1] + wedges[i]) / 2.0 + slack;
+                    angles.push(angle);
+                }
+
+                let widest = children.iter().map(|child| child.radius).fold(0f32, f32::max);
+                Footprint { radius: (ring + widest).max(NODE_RADIUS), ring, angles, children }
+            }
+        }
+    }
+}
+
+// Children are placed on a circle below their parent, each subtree sized by its own footprint
+pub struct ConeLayout {
+    pub spacing: f32,
+}
+
+impl Layout for ConeLayout {
+    fn extent(&self, wood: &Wood) -> f32 {
+        match wood.wood.get_root().upgrade() {
+            Some(root) => Footprint::cone(&root, self.spacing).radius * 2.0,
+            None => 0.0,
+        }
+    }
+
+    fn place_wood(&self, wood: &Wood, origin: Point, placement: &mut Placement) -> Point {
+        let root = match wood.wood.get_root().upgrade() {
+            Some(root) => root,
+            None => return above(wood, origin),
+        };
+        let footprint = Footprint::cone(&root, self.spacing);
+
+        let mut nodes = VecDeque::new();
+        nodes.push_back(((origin.x, origin.y, origin.z), root, &footprint));
+
+        while let Some(((x, y, z), node_arc, footprint)) = nodes.pop_front() {
+            let node = node_arc.read().unwrap();
+            placement.nodes.push((node.read_path(), node_arc.clone(), Point::new(x, y, z)));
+
+            let children: Vec<Arc<Node>> = node.visible_children().iter().filter_map(|child| child.upgrade()).collect();
+            let size = children.len();
+            if size == 0 {
+                continue;
+            } else if size == 1 {
+                placement.edges.push((Point::new(x, y, z), Point::new(x, y - wood.base_gap, z)));
+                nodes.push_back(((x, y - wood.base_gap, z), children[0].clone(), &footprint.children[0]));
+                continue;
+            }
+
+            let scale = footprint.ring;
+            placement.rings.push((Point::new(x, y - wood.base_gap, z), scale));
+
+            // Keep sibling order around the circle
+            for ((angle, kid), kid_footprint) in footprint.angles.iter().zip(children.into_iter()).zip(footprint.children.iter()) {
+                let kid_x = x - scale * angle.cos();
+                let kid_y = y - wood.base_gap;
+                let kid_z = z - scale * angle.sin();
+
+                placement.edges.push((Point::new(x, y, z), Point::new(kid_x, kid_y, kid_z)));
+                nodes.push_back(((kid_x, kid_y, kid_z), kid, kid_footprint));
+            }
+        }
+        above(wood, origin)
+    }
+}
+
+// Subtree of the tidy layout (Reingold-Tilford with Walker's spreading). Children are packed
+// left to right as close as their contours allow, smaller subtrees squeezed between larger ones
+// are spread evenly over the room left between them, and the parent is centered above its first
+// and last child
+pub struct TidyShape {
+    // Offsets of the children along the level, relative to the node
+    pub offsets: Vec<f32>,
+    pub children: Vec<TidyShape>,
+    // Leftmost and rightmost node on each level of the subtree, relative to the node
+    pub contour: Vec<(f32, f32)>,
+}
+
+impl TidyShape {
+    pub fn tidy<N: GodsnodeProto>(node: &Arc<RwLock<N>>, spacing: f32) -> TidyShape {
+        let children: Vec<TidyShape> = node.read().unwrap().visible_children().iter()
+            .filter_map(|child| child.upgrade())
+            .map(|child| TidyShape::tidy(&child, spacing))
+            .collect();
+
+        // Node centers on a level stay this far apart
+        let distance = NODE_RADIUS * 2.0 + spacing;
+        let mut positions: Vec<f32> = Vec::new();
+        // Placed child holding the rightmost node of each level
+        let mut owners: Vec<usize> = Vec::new();
+        for (index, child) in children.iter().enumerate() {
+            let mut x = positions.last().cloned().unwrap_or(0.0);
+            for (level, (left, _)) in child.contour.iter().enumerate().take(owners.len()) {
+                let owner = owners[level];
+                let shift = positions[owner] + children[owner].contour[level].1 + distance - (x + left);
+                if shift <= 0.0 {
+                    continue;
+                }
+                x += shift;
+                // Siblings between the owner and the new subtree are shallower than this level,
+                // they take a growing share of the shift so they end up evenly spaced
+                let between = (index - owner) as f32;
+                for (k, position) in positions.iter_mut().enumerate().skip(owner + 1) {
+                    *position += shift * (k - owner) as f32 / between;
+                }
+            }
+            positions.push(x);
+            for level in 0..child.contour.len() {
+                if level < owners.len() {
+                    owners[level] = index;
+                } else {
+                    owners.push(index);
+                }
+            }
+        }
+
+        let mut merged: Vec<(f32, f32)> = Vec::new();
+        for (child, x) in children.iter().zip(positions.iter()) {
+            for (level, (left, right)) in child.contour.iter().enumerate() {
+                if level < merged.len() {
+                    merged[level] = (merged[level].0.min(left + x), merged[level].1.max(right + x));
+                } else {
+                    merged.push((left + x, right + x));
+                }
+            }
+        }
+
+        let center = match (positions.first(), positions.last()) {
+            (Some(first), Some(last)) => (first + last) / 2.0,
+            _ => 0.0,
+        };
+        let mut contour = vec![(0f32, 0f32)];
+        contour.extend(merged.iter().map(|(left, right)| (left - center, right - center)));
+        TidyShape {
+            offsets: positions.iter().map(|x| x - center).collect(),
+            children,
+            contour,
+        }
+    }
+
+    // Distance between the outermost node centers
+    pub fn width(&self) -> f32 {
+        let left = self.contour.iter().map(|c| c.0).fold(0f32, f32::min);
+        let right = self.contour.iter().map(|c| c.1).fold(0f32, f32::max);
+        right - left
+    }
+}
+
+// Flat tree growing from the root in one direction, levels are base_gap apart
+pub struct TidyLayout {
+    pub spacing: f32,
+    pub orientation: Orientation,
+}
+
+impl TidyLayout {
+    // Offset along the level and level depth to the scene plane
+    fn point(&self, origin: Point, along: f32, depth: f32) -> Point {
+        match self.orientation {
+            Orientation::TopDown => Point::new(origin.x + along, origin.y - depth, origin.z),
+            Orientation::LeftRight => Point::new(origin.x + depth, origin.y - along, origin.z),
+            Orientation::BottomUp => Point::new(origin.x + along, origin.y + depth, origin.z),
+        }
+    }
+}
+
+impl Layout for TidyLayout {
+    fn extent(&self, wood: &Wood) -> f32 {
+        let root = match wood.wood.get_root().upgrade() {
+            Some(root) => root,
+            None => return 0.0,
+        };
+        let shape = TidyShape::tidy(&root, self.spacing);
+        let depth = (shape.contour.len() - 1) as f32 * wood.base_gap;
+        (shape.width() + NODE_RADIUS * 2.0).max(depth + NODE_RADIUS * 2.0)
+    }
+
+    fn place_wood(&self, wood: &Wood, origin: Point, placement: &mut Placement) -> Point {
+        // Behind the root, against the growth of the tree
+        let title = self.point(origin, 0.0, -wood.base_gap);
+        let root = match wood.wood.get_root().upgrade() {
+            Some(root) => root,
+            None => return title,
+        };
+        let shape = TidyShape::tidy(&root, self.spacing);
+
+        let mut nodes = VecDeque::new();
+        nodes.push_back((0f32, 0f32, root, &shape));
+
+        while let Some((along, depth, node_arc, shape)) = nodes.pop_front() {
+            let node = node_arc.read().unwrap();
+            let point = self.point(origin, along, depth);
+            placement.nodes.push((node.read_path(), node_arc.clone(), point));
+
+            let children = node.visible_children().iter().filter_map(|child| child.upgrade());
+            for ((kid, offset), kid_shape) in children.zip(shape.offsets.iter()).zip(shape.children.iter()) {
+                let kid_along = along + offset;
+                let kid_depth = depth + wood.base_gap;
+                placement.edges.push((point, self.point(origin, kid_along, kid_depth)));
+                nodes.push_back((kid_along, kid_depth, kid, kid_shape));
+            }
+        }
+        title
+    }
+}
+
+// Rings around the root, one per depth, base_gap apart
+pub struct RadialLayout {
+    pub weight: Option<String>,
+    // Room between neighbours on a ring
+    pub spacing: f32,
+    // Draw every node as a sector of its ring instead of a point
+    pub sunburst: bool,
+}
+
+// Rings of the visible depths of a wood and the angular span of every visible node by id
+struct Rings {
+    // Radius the nodes of each depth sit at, the root sits at the center
+    radii: Vec<f32>,
+    // Width of the sunburst bands, the band of a depth is centered on its radius
+    band: f32,
+    spans: HashMap<u64, (f32, f32)>,
+}
+
+impl RadialLayout {
+    // Visible nodes by depth starting with the root, collapsed nodes end their branch
+    fn levels(wood: &Wood) -> Vec<Vec<Arc<Node>>> {
+        let mut levels = Vec::new();
+        let mut current: Vec<Arc<Node>> = wood.wood.get_root().upgrade().into_iter().collect();
+        while !current.is_empty() {
+            let next = current.iter()
+                .flat_map(|node| node.read().unwrap().visible_children().iter().filter_map(|child| child.upgrade()).collect::<Vec<_>>())
+                .collect();
+            levels.push(current);
+            current = next;
+        }
+        levels
+    }
+
+    // Radius of the outermost visible ring, or of the outer edge of the sunburst
+    fn outer(&self, rings: &Rings) -> f32 {
+        if self.sunburst {
+            rings.radii.len() as f32 * rings.band
+        } else {
+            rings.radii.last().cloned().unwrap_or(0.0)
+        }
+    }
+
+    // Rings start base_gap apart and grow until their nodes fit side by side, spacing apart.
+    // Every node gets at least the angle it needs on its ring, or the sum of what its children
+    // need, the rest of the span of a parent is split between its children by the weight of their
+    // visible subtree, a collapsed node weighs as a leaf. When the needs of all nodes exceed the
+    // circle, all rings grow alike until they fit.
+    fn rings(&self, wood: &Wood, levels: &[Vec<Arc<Node>>]) -> Rings {
+        let room = 2.0 * NODE_RADIUS + self.spacing;
+        // Smallest radius with count nodes room apart
+        let fit = |count: usize| if count > 1 { room / (2.0 * (PI / count as f32).sin()) } else { 0.0 };
+        let mut band = wood.base_gap;
+        let mut radii = vec![0.0f32; levels.len()];
+        if self.sunburst {
+            for (d, level) in levels.iter().enumerate().skip(1) {
+                band = band.max(fit(level.len()) / (d as f32 + 0.5));
+            }
+            for (d, radius) in radii.iter_mut().enumerate().skip(1) {
+                *radius = (d as f32 + 0.5) * band;
+            }
+        } else {
+            for d in 1..levels.len() {
+                radii[d] = (radii[d - 1] + wood.base_gap).max(fit(levels[d].len()));
+            }
+        }
+
+        let visible = |node: &NodeProto| -> Vec<u64> {
+            node.visible_children().iter().filter_map(|kid| kid.upgrade()).map(|kid| kid.read().unwrap().id).collect()
+        };
+        let mut weights: HashMap<u64, f64> = HashMap::new();
+        let mut needs: HashMap<u64, f32> = HashMap::new();
+        for (d, level) in levels.iter().enumerate().rev() {
+            // Angle between the centers of two nodes room apart on the ring
+            let angle = if d == 0 { 0.0 } else { 2.0 * (room / (2.0 * radii[d])).min(1.0).asin() };
+            for node in level.iter() {
+                let node = node.read().unwrap();
+                let children = visible(&node);
+                let weight = if children.is_empty() {
+                    leaf_weight(&node, &self.weight)
+                } else {
+                    children.iter().map(|id| weights[id]).sum()
+                };
+                weights.insert(node.id, weight);
+                needs.insert(node.id, children.iter().map(|id| needs[id]).sum::<f32>().max(angle));
+            }
+        }
+
+        let root = levels.first().and_then(|level| level.first()).map(|root| root.read().unwrap().id);
+        let scale = root.map(|root| needs[&root] / (2.0 * PI)).unwrap_or(0.0).max(1.0);
+        for radius in radii.iter_mut() {
+            *radius *= scale;
+        }
+        band *= scale;
+
+        let mut spans = HashMap::new();
+        for (d, level) in levels.iter().enumerate() {
+            for node in level.iter() {
+                let node = node.read().unwrap();
+                let (start, end) = if d == 0 {
+                    (0.0, 2.0 * PI)
+                } else {
+                    match spans.get(&node.id) {
+                        Some(span) => *span,
+                        None => continue,
+                    }
+                };
+                spans.insert(node.id, (start, end));
+
+                let children = visible(&node);
+                let total: f64 = children.iter().map(|id| weights[id]).sum();
+                let needed: f32 = children.iter().map(|id| needs[id] / scale).sum();
+                let rest = (end - start - needed).max(0.0);
+                let mut cursor = start;
+                for id in children.iter() {
+                    // Without any weight below, children split the rest evenly
+                    let share = if total > 0.0 { weights[id] / total } else { 1.0 / children.len() as f64 };
+                    let next = cursor + needs[id] / scale + rest * share as f32;
+                    spans.insert(*id, (cursor, next));
+                    cursor = next;
+                }
+            }
+        }
+        Rings { radii, band, spans }
+    }
+}
+
+impl Layout for RadialLayout {
+    fn extent(&self, wood: &Wood) -> f32 {
+        let levels = RadialLayout::levels(wood);
+        (self.outer(&self.rings(wood, &levels)) + NODE_RADIUS) * 2.0
+    }
+
+    fn place_wood(&self, wood: &Wood, origin: Point, placement: &mut Placement) -> Point {
+        let levels = RadialLayout::levels(wood);
+        let rings = self.rings(wood, &levels);
+        let mut positions = HashMap::new();
+        for (d, level) in levels.iter().enumerate() {
+            for node_arc in level.iter() {
+                let node = node_arc.read().unwrap();
+                let (start, end) = match rings.spans.get(&node.id) {
+                    Some(span) => *span,
+                    None => continue,
+                };
+
+                let point = if d == 0 { origin } else { polar(origin, rings.radii[d], (start + end) / 2.0) };
+                if self.sunburst {
+                    let inner = if d == 0 { 0.0 } else { rings.radii[d] - rings.band / 2.0 };
+                    placement.sectors.push(Sector { center: origin, inner, outer: rings.radii[d] + rings.band / 2.0, start, end });
+                } else {
+                    // The first parent is the tree parent, placed one level up
+                    let parent = node.get_parents().first().and_then(|parent| parent.upgrade());
+                    if let Some(parent) = parent.and_then(|parent| positions.get(&parent.read().unwrap().id).cloned()) {
+                        placement.edges.push((parent, point));
+                    }
+                }
+                positions.insert(node.id, point);
+                placement.nodes.push((node.read_path(), node_arc.clone(), point));
+            }
+        }
+        Point::new(origin.x, origin.y + self.outer(&rings) + wood.base_gap, origin.z)
+    }
+}
+
+// Rectangle in the x-y plane
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Rect {
+    fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    fn inset(&self, padding: f32) -> Rect {
+        let padding = padding.min(self.width / 2.0).min(self.height / 2.0).max(0.0);
+        Rect { x: self.x + padding, y: self.y + padding, width: self.width - padding * 2.0, height: self.height - padding * 2.0 }
+    }
+}
+
+// Worst aspect ratio of a row of areas laid along a side
+fn worst(row: &[f32], side: f32) -> f32 {
+    let sum: f32 = row.iter().sum();
+    let max = row.iter().cloned().fold(0f32, f32::max);
+    let min = row.iter().cloned().fold(std::f32::MAX, f32::min);
+    (side * side * max / (sum * sum)).max(sum * sum / (side * side * min))
+}
+
+// Squarified tiling, rows of tiles are grown along the shorter side of what is left
+// as long as that keeps their aspect ratios closer to square. Tiles come back in input order.
+fn squarify(weights: &[f64], rect: Rect) -> Vec<Rect> {
+    let (cx, cy) = rect.center();
+    let mut tiles = vec![Rect { x: cx, y: cy, width: 0.0, height: 0.0 }; weights.len()];
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 || rect.width <= 0.0 || rect.height <= 0.0 {
+        return tiles;
+    }
+
+    let scale = (rect.width * rect.height) as f64 / total;
+    let mut order: Vec<usize> = (0..weights.len()).filter(|i| weights[*i] > 0.0).collect();
+    order.sort_by(|a, b| weights[*b].partial_cmp(&weights[*a]).unwrap_or(Ordering::Equal));
+    let areas: Vec<f32> = order.iter().map(|i| (weights[*i] * scale) as f32).collect();
+
+    let mut free = rect;
+    let mut start = 0;
+    while start < order.len() {
+        let side = free.width.min(free.height);
+        let mut end = start + 1;
+        while end < order.len() && worst(&areas[start..end + 1], side) <= worst(&areas[start..end], side) {
+            end += 1;
+        }
+
+        let row: f32 = areas[start..end].iter().sum();
+        let thickness = row / side;
+        let mut offset = 0.0;
+        for k in start..end {
+            let length = areas[k] / thickness;
+            tiles[order[k]] = if free.width >= free.height {
+                // Column along the left of what is left
+                Rect { x: free.x, y: free.y + offset, width: thickness, height: length }
+            } else {
+                // Row along the bottom
+                Rect { x: free.x + offset, y: free.y, width: length, height: thickness }
+            };
+            offset += length;
+        }
+        free = if free.width >= free.height {
+            Rect { x: free.x + thickness, y: free.y, width: free.width - thickness, height: free.height }
+        } else {
+            Rect { x: free.x, y: free.y + thickness, width: free.width, height: free.height - thickness }
+        };
+        start = end;
+    }
+    tiles
+}
+
+// Squarified treemap, every node is a rectangle with an area by weight holding its children
+pub struct TreemapLayout {
+    pub weight: Option<String>,
+    pub padding: f32,
+}
+
+impl TreemapLayout {
+    // A weight of one covers a square of base_gap sides
+    fn side(&self, wood: &Wood, weights: &HashMap<u64, f64>) -> f32 {
+        let root = wood.wood.get_root().upgrade().map(|root| root.read().unwrap().id);
+        let total = root.and_then(|id| weights.get(&id)).cloned().unwrap_or(0.0);
+        (total.sqrt() as f32).max(1.0) * wood.base_gap
+    }
+}
+
+impl Layout for TreemapLayout {
+    fn extent(&self, wood: &Wood) -> f32 {
+        self.side(wood, &weights(wood, &self.weight))
+    }
+
+    fn place_wood(&self, wood: &Wood, origin: Point, placement: &mut Placement) -> Point {
+        let weights = weights(wood, &self.weight);
+        let side = self.side(wood, &weights);
+        let title = Point::new(origin.x, origin.y + side / 2.0 + wood.base_gap / 2.0, origin.z);
+        let root = match wood.wood.get_root().upgrade() {
+            Some(root) => root,
+            None => return title,
+        };
+
+        let mut nodes = VecDeque::new();
+        nodes.push_back((root, Rect { x: origin.x - side / 2.0, y: origin.y - side / 2.0, width: side, height: side }));
+
+        while let Some((node_arc, rect)) = nodes.pop_front() {
+            let node = node_arc.read().unwrap();
+            let (x, y) = rect.center();
+            placement.nodes.push((node.read_path(), node_arc.clone(), Point::new(x, y, origin.z)));
+            placement.rects.push((Point::new(rect.x, rect.y, origin.z), Point::new(rect.x + rect.width, rect.y + rect.height, origin.z)));
+
+            let children: Vec<Arc<Node>> = node.visible_children().iter().filter_map(|child| child.upgrade()).collect();
+            let shares: Vec<f64> = children.iter().map(|child| weights.get(&child.read().unwrap().id).cloned().unwrap_or(0.0)).collect();
+            let tiles = squarify(&shares, rect.inset(self.padding));
+            for (child, tile) in children.into_iter().zip(tiles.into_iter()) {
+                nodes.push_back((child, tile));
+            }
+        }
+        title
+    }
+}
+
+// Discs of a node in the pack layout, children offsets are relative to the node center
+struct Packing {
+    radius: f32,
+    offsets: Vec<(f32, f32)>,
+}
+
+// Center of a disc of radius r touching the discs a and b, on the left of b -> a
+fn tangent(b: (f64, f64, f64), a: (f64, f64, f64), r: f64) -> (f64, f64) {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let d2 = dx * dx + dy * dy;
+    if d2 <= 0.0 {
+        return (a.0 + r, a.1);
+    }
+    let (a2, b2) = ((a.2 + r).powi(2), (b.2 + r).powi(2));
+    if a2 > b2 {
+        let x = (d2 + b2 - a2) / (2.0 * d2);
+        let y = (b2 / d2 - x * x).max(0.0).sqrt();
+        (b.0 - x * dx - y * dy, b.1 - x * dy + y * dx)
+    } else {
+        let x = (d2 + a2 - b2) / (2.0 * d2);
+        let y = (a2 / d2 - x * x).max(0.0).sqrt();
+        (a.0 + x * dx - y * dy, a.1 + x * dy + y * dx)
+    }
+}
+
+fn intersects(a: (f64, f64, f64), b: (f64, f64, f64)) -> bool {
+    let dr = a.2 + b.2 - 1e-6;
+    dr > 0.0 && dr * dr > (b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)
+}
+
+// Place sibling discs with a front chain (Wang et al., as in d3's packSiblings): every disc
+// touches the pair of the chain closest to the center, and the chain is cut back to the
+// nearest disc it would overlap, then center them in their enclosing disc.
+// Returns the offsets and the radius of the enclosing disc.
+fn pack_siblings(radii: &[f32]) -> (Vec<(f32, f32)>, f32) {
+    let count = radii.len();
+    let mut placed: Vec<(f64, f64, f64)> = radii.iter().map(|r| (0.0, 0.0, *r as f64)).collect();
+    if count > 1 {
+        placed[0].0 = -placed[1].2;
+        placed[1].0 = placed[0].2;
+    }
+    if count > 2 {
+        let (x, y) = tangent(placed[1], placed[0], placed[2].2);
+        placed[2] = (x, y, placed[2].2);
+
+        // Front chain as a ring of indices, starting with the first three discs
+        let mut next = vec![0usize; count];
+        let mut previous = vec![0usize; count];
+        next[..3].copy_from_slice(&[1, 2, 0]);
+        previous[..3].copy_from_slice(&[2, 0, 1]);
+        // Weighted center of the pair a -> b, the pair nearest the origin takes the next disc
+        let score = |placed: &Vec<(f64, f64, f64)>, a: usize, b: usize| {
+            let (a, b) = (placed[a], placed[b]);
+            let ab = a.2 + b.2;
+            if ab <= 0.0 {
+                return a.0 * a.0 + a.1 * a.1;
+            }
+            let (x, y) = ((a.0 * b.2 + b.0 * a.2) / ab, (a.1 * b.2 + b.1 * a.2) / ab);
+            x * x + y * y
+        };
+
+        let (mut a, mut b) = (0usize, 1usize);
+        let mut i = 3;
+        while i < count {
+            let (x, y) = tangent(placed[a], placed[b], placed[i].2);
+            placed[i].0 = x;
+            placed[i].1 = y;
+
+            // Closest disc of the chain overlapping the new one, by length along the chain
+            let (mut j, mut k) = (next[b], previous[a]);
+            let (mut sj, mut sk) = (placed[b].2, placed[a].2);
+            let mut blocked = false;
+            loop {
+                if sj <= sk {
+                    if intersects(placed[j], placed[i]) {
+                        b = j;
+                        next[a] = b;
+                        previous[b] = a;
+                        blocked = true;
+                        break;
+                    }
+                    sj += placed[j].2;
+                    j = next[j];
+                } else {
+                    if intersects(placed[k], placed[i]) {
+                        a = k;
+                        next[a] = b;
+                        previous[b] = a;
+                        blocked = true;
+                        break;
+                    }
+                    sk += placed[k].2;
+                    k = previous[k];
+                }
+                if j == next[k] {
+                    break;
+                }
+            }
+            // Try again against the shortened chain
+            if blocked {
+                continue;
+            }
+
+            previous[i] = a;
+            next[i] = b;
+            next[a] = i;
+            previous[b] = i;
+            let mut best = score(&placed, a, i);
+            let mut c = next[i];
+            while c != i {
+                let candidate = score(&placed, c, next[c]);
+                if candidate < best {
+                    a = c;
+                    best = candidate;
+                }
+                c = next[c];
+            }
+            b = next[a];
+            i += 1;
+        }
+    }
+    let placed: Vec<(f32, f32, f32)> = placed.iter().map(|(x, y, r)| (*x as f32, *y as f32, *r as f32)).collect();
+
+    if placed.is_empty() {
+        return (Vec::new(), 0.0);
+    }
+    let min_x = placed.iter().map(|(x, _, r)| x - r).fold(std::f32::MAX, f32::min);
+    let max_x = placed.iter().map(|(x, _, r)| x + r).fold(std::f32::MIN, f32::max);
+    let min_y = placed.iter().map(|(_, y, r)| y - r).fold(std::f32::MAX, f32::min);
+    let max_y = placed.iter().map(|(_, y, r)| y + r).fold(std::f32::MIN, f32::max);
+    let (cx, cy) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let offsets: Vec<(f32, f32)> = placed.iter().map(|(x, y, _)| (x - cx, y - cy)).collect();
+    let radius = placed.iter().zip(offsets.iter())
+        .map(|((_, _, r), (x, y))| (x * x + y * y).sqrt() + r)
+        .fold(0f32, f32::max);
+    (offsets, radius)
+}
+
+// Circle packing, every node is a disc with an area by weight holding its children
+pub struct PackLayout {
+    pub weight: Option<String>,
+    pub padding: f32,
+}
+
+impl PackLayout {
+    // Discs of every node by id, sized bottom-up over the nodes by depth
+    fn packings(&self, wood: &Wood) -> HashMap<u64, Packing> {
+        let weights = weights(wood, &self.weight);
+        let mut packings: HashMap<u64, Packing> = HashMap::new();
+        for d in (1..wood.wood.get_depth() + 1).rev() {
+            for node in level(wood, d).iter() {
+                let node = node.read().unwrap();
+                // Collapsed nodes keep the area of their subtree
+                let children = if node.is_collapsed() { Vec::new() } else { child_ids(&node) };
+                let packing = if children.is_empty() {
+                    // A weight of one covers a disc of base_gap diameter
+                    let weight = weights.get(&node.id).cloned().unwrap_or(0.0) as f32;
+                    Packing { radius: weight.sqrt() * wood.base_gap / 2.0, offsets: Vec::new() }
+                } else {
+                    let radii: Vec<f32> = children.iter().map(|id| packings.get(id).map(|p| p.radius).unwrap_or(0.0)).collect();
+                    let (offsets, radius) = pack_siblings(&radii);
+                    Packing { radius: radius + self.padding, offsets }
+                };
+                packings.insert(node.id, packing);
+            }
+        }
+        packings
+    }
+
+    fn radius(wood: &Wood, packings: &HashMap<u64, Packing>) -> f32 {
+        let root = wood.wood.get_root().upgrade().map(|root| root.read().unwrap().id);
+        root.and_then(|id| packings.get(&id).map(|p| p.radius)).unwrap_or(0.0).max(NODE_RADIUS)
+    }
+}
+
+impl Layout for PackLayout {
+    fn extent(&self, wood: &Wood) -> f32 {
+        PackLayout::radius(wood, &self.packings(wood)) * 2.0
+    }
+
+    fn place_wood(&self, wood: &Wood, origin: Point, placement: &mut Placement) -> Point {
+        let packings = self.packings(wood);
+        let title = Point::new(origin.x, origin.y + PackLayout::radius(wood, &packings) + wood.base_gap / 2.0, origin.z);
+        let root = match wood.wood.get_root().upgrade() {
+            Some(root) => root,
+            None => return title,
+        };
+
+        let mut nodes = VecDeque::new();
+        nodes.push_back((root, origin));
+
+        while let Some((node_arc, center)) = nodes.pop_front() {
+            let node = node_arc.read().unwrap();
+            placement.nodes.push((node.read_path(), node_arc.clone(), center));
+            let packing = match packings.get(&node.id) {
+                Some(packing) => packing,
+                None => continue,
+            };
+            placement.discs.push((center, packing.radius));
+
+            let children = node.visible_children().iter().filter_map(|child| child.upgrade());
+            for (child, (x, y)) in children.zip(packing.offsets.iter()) {
+                nodes.push_back((child, Point::new(center.x + x, center.y + y, center.z)));
+            }
+        }
+        title
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ForestConfig;
+    use serde_json::{json, Map, Value};
+
+    fn leaves(count: usize) -> Value {
+        let mut children = Map::new();
+        for i in 0..count {
+            children.insert(format!("leaf{}", i), json!({}));
+        }
+        json!({ "children": children })
+    }
+
+    fn binary(depth: usize) -> Value {
+        if depth == 0 {
+            return json!({});
+        }
+        json!({ "children": { "left": binary(depth - 1), "right": binary(depth - 1) } })
+    }
+
+    fn named(name: &str, mut tree: Value) -> Value {
+        tree["name"] = json!(name);
+        tree
+    }
+
+    // Forest of the single wood of tree and its root, the forest holds the nodes and must outlive it
+    fn wood(tree: Value) -> (Forest, Arc<Node>) {
+        let mut forest = ForestConfig::new();
+        forest.woods = vec![tree];
+        let woods = Forest::load(&forest).unwrap();
+        let root = woods.ordered()[0].read().unwrap().wood.get_root().upgrade().unwrap();
+        (woods, root)
+    }
+
+    // Wide, deep and skewed fan-outs, the skewed one mixes large and small siblings
+    fn woods(kind: LayoutKind, spacing: f32) -> Forest {
+        let mut skewed = leaves(6);
+        skewed["children"]["big"] = json!({ "children": { "a": leaves(12), "b": leaves(3), "c": {} } });
+        skewed["children"]["mid"] = leaves(4);
+        skewed["children"]["chain"] = json!({ "children": { "x": { "children": { "y": leaves(5) } } } });
+
+        let mut forest = ForestConfig::new();
+        forest.woods = vec![named("wide", leaves(40)), named("deep", binary(6)), named("skewed", skewed)];
+        forest.layout.kind = kind;
+        forest.layout.spacing = spacing;
+        Forest::load(&forest).unwrap()
+    }
+
+    fn positions(placement: &Placement) -> HashMap<String, Point> {
+        placement.nodes.iter().map(|(path, _, point)| (path.clone(), *point)).collect()
+    }
+
+    #[test]
+    fn cone_siblings_keep_spacing() {
+        for spacing in [0.5f32, 2.0, 6.0].iter() {
+            let woods = woods(LayoutKind::Cone, *spacing);
+            let placement = ConeLayout { spacing: *spacing }.place(&woods);
+            let positions = positions(&placement);
+
+            let mut checked = 0;
+            for (_, node, _) in placement.nodes.iter() {
+                let siblings: Vec<(Point, f32)> = node.read().unwrap().visible_children().iter()
+                    .filter_map(|child| child.upgrade())
+                    .map(|child| {
+                        let position = positions[&child.read().unwrap().read_path()];
+                        (position, Footprint::cone(&child, *spacing).radius)
+                    })
+                    .collect();
+                for (i, (a, ra)) in siblings.iter().enumerate() {
+                    for (b, rb) in siblings[i + 1..].iter() {
+                        let distance = ((a.x - b.x).powi(2) + (a.z - b.z).powi(2)).sqrt();
+                        assert!(distance >= ra + rb + spacing - 1e-3,
+                                "siblings at {:?} and {:?} are {} apart, footprints {} and {} with spacing {}",
+                                a, b, distance, ra, rb, spacing);
+                        checked += 1;
+                    }
+                }
+            }
+            assert!(checked > 0);
+        }
+    }
+
+    #[test]
+    fn cone_spacing_moves_nodes() {
+        let place = |spacing: f32| positions(&ConeLayout { spacing }.place(&woods(LayoutKind::Cone, spacing)));
+        let (narrow, wide) = (place(1.0), place(4.0));
+        assert_eq!(narrow.len(), wide.len());
+        assert!(narrow.iter().any(|(path, point)| wide[path] != *point));
+
+        let root = |spacing: f32| {
+            let woods = woods(LayoutKind::Cone, spacing);
+            let wood = woods.ordered()[0].clone();
+            let root = wood.read().unwrap().wood.get_root().upgrade().unwrap();
+            Footprint::cone(&root, spacing).ring
+        };
+        assert!(root(4.0) > root(1.0));
+    }
+
+    #[test]
+    fn tidy_levels_keep_spacing() {
+        let spacing = 2.0;
+        let woods = woods(LayoutKind::Tidy, spacing);
+        let placement = TidyLayout { spacing, orientation: Orientation::TopDown }.place(&woods);
+        for (i, (_, _, a)) in placement.nodes.iter().enumerate() {
+            for (_, _, b) in placement.nodes[i + 1..].iter() {
+                if (a.y - b.y).abs() < 1e-3 {
+                    assert!((a.x - b.x).abs() >= NODE_RADIUS * 2.0 + spacing - 1e-3, "nodes at {:?} and {:?} overlap", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tidy_spreads_small_subtrees_between_large_ones() {
+        let mut tree = json!({ "name": "spread", "children": { "a": leaves(6), "s1": {}, "s2": {}, "b": leaves(6) } });
+        tree["children"]["s3"] = json!({});
+        tree["children"]["c"] = leaves(6);
+        let (_woods, root) = wood(tree);
+
+        let shape = TidyShape::tidy(&root, 2.0);
+        let gaps: Vec<f32> = shape.offsets.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        // a, s1, s2 and b share the room between a and b evenly, so do b, s3 and c
+        assert!((gaps[0] - gaps[1]).abs() < 1e-3 && (gaps[1] - gaps[2]).abs() < 1e-3, "gaps {:?}", gaps);
+        assert!((gaps[3] - gaps[4]).abs() < 1e-3, "gaps {:?}", gaps);
+        // Centered above the first and last child
+        assert!((shape.offsets[0] + shape.offsets[5]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn radial_splits_the_visible_tree() {
+        let (woods, _) = wood(json!({ "name": "ring", "children": { "big": leaves(8), "small": leaves(2) } }));
+        let wood = woods.ordered()[0].clone();
+        let sunburst = RadialLayout { weight: None, spacing: 1.0, sunburst: true };
+        let span = |path: &str| {
+            let wood = wood.read().unwrap();
+            let levels = RadialLayout::levels(&wood);
+            let rings = sunburst.rings(&wood, &levels);
+            let node = levels.iter().flatten().find(|node| node.read().unwrap().read_path() == path).unwrap().clone();
+            let id = node.read().unwrap().id;
+            let (start, end) = rings.spans[&id];
+            (end - start, sunburst.outer(&rings) / wood.base_gap)
+        };
+
+        let (big, rings) = span(".ring.big");
+        assert!((big - 2.0 * PI * 0.8).abs() < 1e-3);
+        assert_eq!(rings, 3.0);
+
+        // Collapsed, the big subtree weighs as a single leaf against the two small ones, past the
+        // angle every node keeps for itself
+        woods.collapse(&[".ring.big".to_string()].iter().cloned().collect());
+        let (big, _) = span(".ring.big");
+        assert!(big > 2.0 * PI * 0.3 && big < 2.0 * PI * 0.4, "collapsed subtree spans {}", big);
+
+        woods.collapse(&[".ring.big".to_string(), ".ring.small".to_string()].iter().cloned().collect());
+        let (_, rings) = span(".ring.big");
+        assert_eq!(rings, 2.0);
+    }
+
+    #[test]
+    fn radial_nodes_keep_spacing() {
+        for spacing in [0.5f32, 2.0, 6.0].iter() {
+            let mut woods = vec![woods(LayoutKind::Radial, *spacing)];
+            // Leaves without weight still get room of their own
+            let mut forest = ForestConfig::new();
+            forest.woods = vec![json!({ "name": "weighted", "children": {
+                "a": { "cost": 0 }, "b": { "cost": 0 }, "c": { "cost": 5 }, "d": { "children": { "e": { "cost": 0 }, "f": { "cost": 0 } } }
+            } })];
+            woods.push(Forest::load(&forest).unwrap());
+
+            for (forest, sunburst) in woods.iter().flat_map(|forest| vec![(forest, false), (forest, true)]) {
+                let layout = RadialLayout { weight: Some("cost".to_string()), spacing: *spacing, sunburst };
+                let placement = layout.place(forest);
+                let points: Vec<Point> = placement.nodes.iter().map(|(_, _, point)| *point).collect();
+                assert!(points.len() > 1);
+                for (i, a) in points.iter().enumerate() {
+                    for b in points[i + 1..].iter() {
+                        let distance = ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt();
+                        assert!(distance >= 2.0 * NODE_RADIUS + spacing - 1e-3,
+                                "nodes at {:?} and {:?} are {} apart with spacing {}", a, b, distance, spacing);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pack_siblings_do_not_overlap() {
+        let radii: Vec<f32> = (0..400).map(|i| 0.5 + (i * 7 % 13) as f32 / 4.0).collect();
+        let (offsets, radius) = pack_siblings(&radii);
+        assert_eq!(offsets.len(), radii.len());
+        for (i, ((x, y), r)) in offsets.iter().zip(radii.iter()).enumerate() {
+            assert!((x * x + y * y).sqrt() + r <= radius + 1e-3);
+            for ((ox, oy), or) in offsets[i + 1..].iter().zip(radii[i + 1..].iter()) {
+                let distance = ((x - ox).powi(2) + (y - oy).powi(2)).sqrt();
+                assert!(distance >= r + or - 1e-2, "discs {} and {} overlap by {}", r, or, r + or - distance);
+            }
+        }
+        // Packed, not strung out, the enclosing disc stays within a few times the total area
+        let area: f32 = radii.iter().map(|r| r * r).sum();
+        assert!(radius * radius < area * 3.0);
+    }
+
+    #[test]
+    fn pack_places_wide_parents() {
+        let (woods, _) = wood(named("wide", leaves(600)));
+        let mut layout = LayoutConfig::new();
+        layout.kind = LayoutKind::Pack;
+        let placement = layout.engine().place(&woods);
+        assert_eq!(placement.nodes.len(), 601);
+    }
+}
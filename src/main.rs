@@ -6,35 +6,170 @@ mod state;
 mod system;
 mod resource;
 mod component;
+mod config;
+mod cli;
+mod export;
+mod input;
+mod watch;
+mod layout;
+mod svg;
+mod diff;
 
-use serde_json;
-
-use std::env;
-use std::fs;
+use std::process;
 
 use amethyst;
-use app::*;
+use clap::{App, AppSettings, Arg, SubCommand};
+use config::{LayoutKind, Orientation};
+use diff::DiffFormat;
+use export::ExportFormat;
+use input::InputFormat;
+use resource::Theme;
 
-fn main() {
-    let mut conf_path = None;
-
-    for arg in env::args().skip(1) {
-        if arg.starts_with("-c") {
-            conf_path = Some(arg.split_at(14).1.to_string());
-        }
-    }
-
-    let conf = if let Some(path) = conf_path {
-        path
-    } else {
-        "./config.json".to_string()
-    };
-    println!("Loading configuration from {}", conf);
+fn input_arg<'a, 'b>(multiple: bool) -> Arg<'a, 'b> {
+    Arg::with_name("input")
+        .help("Tree files, - reads stdin, defaults to the woods of the config file")
+        .multiple(multiple)
+}
+
+fn display_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("display")
+        .long("display")
+        .value_name("FILE")
+        .help("Display config, defaults to config/display.ron")
+}
+
+fn bindings_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("bindings")
+        .long("bindings")
+        .value_name("FILE")
+        .help("Input bindings, defaults to config/input.ron")
+}
 
-    let conf_file = fs::File::open(conf).expect("Failed to read config file");
-    let config = serde_json::from_reader(conf_file).expect("Failed to parse config file");
+fn camera_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("camera")
+        .long("camera")
+        .value_name("VIEW")
+        .help("Svg camera, top (default), front, side or <yaw>,<pitch> in degrees")
+}
+
+fn theme_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("theme")
+        .long("theme")
+        .takes_value(true)
+        .possible_values(Theme::NAMES)
+}
+
+fn layout_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("layout")
+        .long("layout")
+        .takes_value(true)
+        .possible_values(LayoutKind::NAMES)
+}
 
-    run(&config);
+fn orientation_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("orientation")
+        .long("orientation")
+        .takes_value(true)
+        .possible_values(Orientation::NAMES)
 }
 
+fn main() {
+    let matches = App::new("godswood")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Visualization tool for json objects and tree like data structures")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("config")
+             .short("c")
+             .long("config")
+             .value_name("FILE")
+             .global(true)
+             .help("Config file listing the woods, defaults to ./config.json"))
+        .arg(Arg::with_name("input-format")
+             .long("input-format")
+             .takes_value(true)
+             .global(true)
+             .possible_values(InputFormat::NAMES)
+             .help("Format of the tree files, detected from their extension by default, stdin is read as json"))
+        .subcommand(SubCommand::with_name("view")
+                    .about("Show the woods in a window")
+                    .arg(input_arg(true))
+                    .arg(display_arg())
+                    .arg(bindings_arg())
+                    .arg(theme_arg())
+                    .arg(layout_arg())
+                    .arg(orientation_arg())
+                    .arg(Arg::with_name("depth")
+                         .long("depth")
+                         .value_name("N")
+                         .help("Expand the woods down to N levels, deeper nodes start collapsed"))
+                    .arg(Arg::with_name("focus")
+                         .long("focus")
+                         .value_name("PATH")
+                         .help("Select the node best matching PATH and fly the camera to it"))
+                    .arg(Arg::with_name("watch")
+                         .short("w")
+                         .long("watch")
+                         .help("Reload the woods when their files change")))
+        .subcommand(SubCommand::with_name("stats")
+                    .about("Print node counts and depth of the woods")
+                    .arg(input_arg(true)))
+        .subcommand(SubCommand::with_name("export")
+                    .about("Export the woods, svg exports need no display")
+                    .arg(input_arg(true))
+                    .arg(Arg::with_name("format")
+                         .short("f")
+                         .long("format")
+                         .default_value("json")
+                         .possible_values(ExportFormat::NAMES))
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("FILE")
+                         .help("Defaults to stdout"))
+                    .arg(camera_arg())
+                    .arg(layout_arg())
+                    .arg(orientation_arg())
+                    .arg(theme_arg()))
+        .subcommand(SubCommand::with_name("diff")
+                    .about("Compare two versions of the woods, nodes are matched by path")
+                    .arg(Arg::with_name("old")
+                         .required(true)
+                         .help("Tree file of the old version"))
+                    .arg(Arg::with_name("new")
+                         .required(true)
+                         .help("Tree file of the new version"))
+                    .arg(Arg::with_name("format")
+                         .short("f")
+                         .long("format")
+                         .default_value("text")
+                         .possible_values(DiffFormat::NAMES))
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("FILE")
+                         .help("Defaults to stdout"))
+                    .arg(Arg::with_name("view")
+                         .long("view")
+                         .help("Show the merged wood colored by change after the report"))
+                    .arg(camera_arg())
+                    .arg(layout_arg())
+                    .arg(orientation_arg())
+                    .arg(theme_arg())
+                    .arg(display_arg())
+                    .arg(bindings_arg()))
+        .subcommand(SubCommand::with_name("validate")
+                    .about("Check the woods parse, exits with 2 when they do not and 3 when they cannot be read")
+                    .arg(input_arg(true)))
+        .get_matches();
 
+    // Without a subcommand the woods of the config file are shown
+    let code = match matches.subcommand() {
+        ("view", Some(matches)) => cli::view(matches),
+        ("stats", Some(matches)) => cli::stats(matches),
+        ("export", Some(matches)) => cli::export(matches),
+        ("diff", Some(matches)) => cli::diff(matches),
+        ("validate", Some(matches)) => cli::validate(matches),
+        _ => cli::view(&matches),
+    };
+    process::exit(code);
+}
@@ -93,6 +93,38 @@ impl Error for CodecError {
 	}
 }
 
+#[derive(Debug)]
+pub struct ParseError {
+    message: String,
+    // The document could not be read, it was never parsed
+    io: bool,
+}
+
+impl ParseError {
+    pub fn new(message: String) -> ParseError {
+        ParseError { message, io: false }
+    }
+
+    pub fn io(message: String) -> ParseError {
+        ParseError { message, io: true }
+    }
+
+    pub fn is_io(&self) -> bool {
+        self.io
+    }
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		fmt.write_str(&self.message)
+	}
+}
+impl Error for ParseError {
+	fn description(&self) -> &str {
+		&self.message
+	}
+}
+
 /*
 #[allow(dead_code)]
 pub async fn sleep(sleep_ms: u64) -> AsyncRes {
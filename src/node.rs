@@ -1,5 +1,5 @@
 use std::sync::{Arc, Weak, RwLock};
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 use serde_json::Value;
 use crate::misc::*;
 
@@ -10,12 +10,25 @@ pub trait GodsnodeProto {
     fn new() -> Self;
     fn get_children(&self) -> &Vec<Weak<RwLock<Self>>>;
     fn get_parents(&self) -> &Vec<Weak<RwLock<Self>>>;
+    fn get_links(&self) -> &Vec<Weak<RwLock<Self>>>;
     fn add_parent(&mut self, node: Weak<Godsnode<Self>>);
     fn add_child(&mut self, node: Weak<Godsnode<Self>>);
+    fn add_link(&mut self, node: Weak<Godsnode<Self>>);
+    fn is_collapsed(&self) -> bool;
+
+    // Children shown by the layouts, none while the node is collapsed
+    fn visible_children(&self) -> &[Weak<RwLock<Self>>] {
+        if self.is_collapsed() {
+            &[]
+        } else {
+            self.get_children()
+        }
+    }
 }
 
 
-pub type GodsnodeStore<T> = HashMap<u64, Arc<Godsnode<T>>>;
+// Keyed by id, ids are handed out in insertion order
+pub type GodsnodeStore<T> = BTreeMap<u64, Arc<Godsnode<T>>>;
 pub type GodsnodeIndexStore = HashMap<String, u64>;
 
 pub struct GodsstoreProto<T> where T: GodsnodeProto {
@@ -29,7 +42,7 @@ impl<T: GodsnodeProto> GodsstoreProto<T> {
     pub fn new() -> Arc<Godsstore<T>> {
         Arc::new(RwLock::new(GodsstoreProto {
             id: 0,
-            store: HashMap::new(),
+            store: BTreeMap::new(),
             index: HashMap::new(),
         }))
     }
@@ -43,6 +56,8 @@ pub trait GodsstoreOps<T> where T: GodsnodeProto {
     fn add_leaf_node(&self, name: &String, raw: &Value) -> Arc<Node>;
     fn update_index(&self, name: &String, index: u64);
     fn get_weak_node(&self, path: &String) -> Option<Weak<Node>>;
+    fn get_attribute(&self, path: &String, key: &str) -> Option<Value>;
+    fn search(&self, query: &str) -> Vec<String>;
 }
 
 pub enum GodsnodeType {
@@ -55,6 +70,37 @@ pub enum GodsnodeClass {
     General,
 }
 
+// Json type of the value a node was built from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GodsnodeValueType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl GodsnodeValueType {
+    pub fn of(raw: &Value) -> GodsnodeValueType {
+        match raw {
+            Value::Null => GodsnodeValueType::Null,
+            Value::Bool(_) => GodsnodeValueType::Bool,
+            Value::Number(_) => GodsnodeValueType::Number,
+            Value::String(_) => GodsnodeValueType::String,
+            Value::Array(_) => GodsnodeValueType::Array,
+            Value::Object(_) => GodsnodeValueType::Object,
+        }
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        match self {
+            GodsnodeValueType::Array | GodsnodeValueType::Object => false,
+            _ => true,
+        }
+    }
+}
+
 
 pub struct InitNodeQ<T> where T: GodsnodeProto {
     pub app_meta: GodswoodMeta,
@@ -75,11 +121,7 @@ impl GodswoodMeta {
 
     pub fn parse_app_name(path: &String) -> Option<String> {
         if !path.starts_with('.') { return None }
-        let tokens: Vec<&str> = path.split('.').collect();
-        if tokens.len() > 1 && tokens[1].len() > 0 {
-            return Some(tokens[1].to_string());
-        }
-        None
+        GodsnodePath::split(path).into_iter().next().filter(|name| !name.is_empty())
     }
 }
 
@@ -98,10 +140,52 @@ impl GodsnodePath {
     }
 
     pub fn append(&mut self, name: &String) {
-        self.path.push_str(&(".".to_owned() + name));
+        self.path.push_str(&(".".to_owned() + &GodsnodePath::escape(name)));
         self.depth += 1;
     }
 
+    // Names can hold dots, e.g. object keys like "example.com", inside paths they are escaped
+    // with a backslash so they never pass for a separator
+    pub fn escape(name: &str) -> String {
+        name.replace('\\', "\\\\").replace('.', "\\.")
+    }
+
+    // Names along a path, unescaped, starting with the root
+    pub fn split(path: &str) -> Vec<String> {
+        let path = path.trim_start_matches('.');
+        let mut names = Vec::new();
+        let mut name = String::new();
+        let mut chars = path.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => name.extend(chars.next()),
+                '.' => names.push(std::mem::take(&mut name)),
+                _ => name.push(c),
+            }
+        }
+        names.push(name);
+        names
+    }
+
+    // Parent path and escaped last name of a path, roots have no parent
+    pub fn split_last(path: &str) -> (Option<&str>, &str) {
+        let mut separator = None;
+        let mut escaped = false;
+        for (i, c) in path.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '.' => separator = Some(i),
+                _ => {}
+            }
+        }
+        match separator {
+            Some(0) => (None, &path[1..]),
+            None => (None, path),
+            Some(i) => (Some(&path[..i]), &path[i + 1..]),
+        }
+    }
+
     pub fn new(root: String) -> GodsnodePath {
         GodsnodePath {
             path: root,
@@ -129,10 +213,56 @@ pub struct NodeProto {
     pub name: String,
     pub display_name: String,
     pub node_type: GodsnodeType,
+    // The first parent is the tree parent, the rest are parents linked by reference
     pub parents: Vec<Weak<Node>>,
     pub children: Vec<Weak<Node>>,
+    // Extra children linked by reference, they are placed under their tree parent
+    pub links: Vec<Weak<Node>>,
     pub service_type: GodsnodeClass,
+    pub value_type: GodsnodeValueType,
+    // Raw fields of the node except its children
+    pub attributes: JsonMap,
     pub app_meta_map: GodswoodMetaMap,
+    // Children are left out of the layouts while set
+    pub collapsed: bool,
+}
+
+impl NodeProto {
+    pub fn get_attribute(&self, key: &str) -> Option<&Value> {
+        self.attributes.get(key)
+    }
+
+    pub fn get_attribute_f64(&self, key: &str) -> Option<f64> {
+        self.attributes.get(key).and_then(|v| v.as_f64())
+    }
+
+    // Dotted path of the node in its wood, available once the wood nodes are initialized
+    pub fn read_path(&self) -> String {
+        match self.app_meta_map.values().next() {
+            Some(meta) => meta.path.read(),
+            None => format!(".{}", GodsnodePath::escape(&self.name)),
+        }
+    }
+
+    // Path, display name and attributes, one per line
+    pub fn describe(&self) -> String {
+        let mut lines = vec![self.read_path(), self.display_name.clone()];
+        for (key, value) in self.attributes.iter() {
+            match value {
+                Value::String(text) => lines.push(format!("{}: {}", key, text)),
+                _ => lines.push(format!("{}: {}", key, value)),
+            }
+        }
+        lines.join("\n")
+    }
+
+    // Number of nodes below this one in its wood
+    pub fn descendants(&self) -> usize {
+        self.children.iter()
+            .filter_map(|kid| kid.upgrade())
+            .map(|kid| 1 + kid.read().unwrap().descendants())
+            .sum()
+    }
 }
 
 impl GodsnodeProto for NodeProto {
@@ -144,8 +274,12 @@ impl GodsnodeProto for NodeProto {
             node_type: GodsnodeType::Node,
             parents: Vec::new(),
             children: Vec::new(),
+            links: Vec::new(),
             service_type: GodsnodeClass::General,
+            value_type: GodsnodeValueType::Object,
+            attributes: JsonMap::new(),
             app_meta_map: HashMap::new(),
+            collapsed: false,
         }
     }
     fn get_children(&self) -> &Vec<Weak<RwLock<Self>>> {
@@ -154,12 +288,21 @@ impl GodsnodeProto for NodeProto {
     fn get_parents(&self) -> &Vec<Weak<RwLock<Self>>> {
         &self.parents
     }
+    fn get_links(&self) -> &Vec<Weak<RwLock<Self>>> {
+        &self.links
+    }
     fn add_parent(&mut self, node: Weak<Godsnode<Self>>) {
         self.parents.push(node);
     }
     fn add_child(&mut self, node: Weak<Godsnode<Self>>) {
         self.children.push(node);
     }
+    fn add_link(&mut self, node: Weak<Godsnode<Self>>) {
+        self.links.push(node);
+    }
+    fn is_collapsed(&self) -> bool {
+        self.collapsed
+    }
 }
 
 pub type Store = Godsstore<NodeProto>;
@@ -182,6 +325,13 @@ impl GodsstoreOps<NodeProto> for Arc<Godsstore<NodeProto>> {
             state.name = name;
             state.display_name = raw.get_str("display_name", "new node");
             state.node_type = GodsnodeType::Node;
+            state.value_type = GodsnodeValueType::of(raw);
+            if let Some(fields) = raw.as_object() {
+                state.attributes = fields.iter()
+                    .filter(|(key, _)| key.as_str() != "children")
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+            }
         }
         node
     }
@@ -191,6 +341,13 @@ impl GodsstoreOps<NodeProto> for Arc<Godsstore<NodeProto>> {
         {
             let mut state = node.write().unwrap();
             state.node_type = GodsnodeType::Leaf;
+            if state.value_type.is_scalar() {
+                state.attributes.insert("value".to_string(), raw.clone());
+                state.display_name = match raw.as_str() {
+                    Some(v) => format!("{}: {}", name, v),
+                    None => format!("{}: {}", name, raw),
+                };
+            }
         }
         node
     }
@@ -218,6 +375,129 @@ impl GodsstoreOps<NodeProto> for Arc<Godsstore<NodeProto>> {
         }
         None
     }
+
+    fn get_attribute(&self, path: &String, key: &str) -> Option<Value> {
+        let node = self.get_weak_node(path)?.upgrade()?;
+        let node = node.read().unwrap();
+        node.get_attribute(key).cloned()
+    }
+
+    // Paths of the nodes matching the query, ignoring case, best first: the exact path, the exact
+    // name or display name, paths starting with the query, then names, display names or paths
+    // containing it, then names holding its characters in order. Matches of the same kind keep the insertion order.
+    fn search(&self, query: &str) -> Vec<String> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        // Paths can be given without the leading dot
+        let dotted = if query.starts_with('.') { query.clone() } else { format!(".{}", query) };
+
+        let state = self.read().unwrap();
+        let mut found: Vec<(u8, u64, String)> = Vec::new();
+        for (path, id) in state.index.iter() {
+            let node = match state.store.get(id) {
+                Some(node) => node.read().unwrap(),
+                None => continue,
+            };
+            let lower = path.to_lowercase();
+            let name = node.name.to_lowercase();
+            let display_name = node.display_name.to_lowercase();
+            let rank = if lower == dotted {
+                0
+            } else if name == query || display_name == query {
+                1
+            } else if lower.starts_with(&dotted) {
+                2
+            } else if name.contains(&query) || display_name.contains(&query) || lower.contains(&query) {
+                3
+            } else if fuzzy_match(&name, &query) || fuzzy_match(&display_name, &query) {
+                4
+            } else {
+                continue;
+            };
+            found.push((rank, *id, path.clone()));
+        }
+        found.sort();
+        found.into_iter().map(|(_, _, path)| path).collect()
+    }
+}
+
+// Whether all characters of the query appear in the text in the same order
+fn fuzzy_match(text: &str, query: &str) -> bool {
+    let mut chars = text.chars();
+    query.chars().all(|c| chars.any(|t| t == c))
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ForestConfig;
+    use crate::tree::Forest;
+    use serde_json::json;
+
+    fn forest() -> Forest {
+        let mut config = ForestConfig::new();
+        config.woods = vec![
+            json!({ "name": "shop", "children": {
+                "cart": { "display_name": "Basket", "children": { "items": {}, "total": {} } },
+                "carton": {},
+                "scart": {},
+                "catalog": { "children": { "art": {} } },
+                "checkout": { "children": { "card": {} } },
+            } }),
+            json!({ "name": "workshop", "children": { "cart": {} } }),
+        ];
+        Forest::load(&config).unwrap()
+    }
+
+    // Found paths split into the given group sizes, each group sorted as matches of the same
+    // rank follow the insertion order
+    fn ranks(found: Vec<String>, sizes: &[usize]) -> Vec<Vec<String>> {
+        assert_eq!(found.len(), sizes.iter().sum::<usize>(), "found {:?}", found);
+        let mut found = found.into_iter();
+        sizes.iter().map(|size| {
+            let mut group: Vec<String> = found.by_ref().take(*size).collect();
+            group.sort();
+            group
+        }).collect()
+    }
+
+    #[test]
+    fn search_ranks_exact_paths_then_prefixes_then_substrings() {
+        let woods = forest();
+        let exact = vec![".shop.cart"];
+        let prefixes = vec![".shop.cart.items", ".shop.cart.total", ".shop.carton"];
+        assert_eq!(ranks(woods.store.search("shop.cart"), &[1, 3, 1]), vec![exact.clone(), prefixes.clone(), vec![".workshop.cart"]]);
+        // With the leading dot the query only matches paths from the start of a name
+        for query in [".shop.cart", " .Shop.Cart "].iter() {
+            assert_eq!(ranks(woods.store.search(query), &[1, 3]), vec![exact.clone(), prefixes.clone()], "query {:?}", query);
+        }
+    }
+
+    #[test]
+    fn search_ranks_names_before_substrings() {
+        let woods = forest();
+        // Same rank, the wood loaded first comes first
+        let found = woods.store.search("cart");
+        assert_eq!(found[..2], [".shop.cart", ".workshop.cart"]);
+        assert_eq!(ranks(found[2..].to_vec(), &[4]), vec![vec![".shop.cart.items", ".shop.cart.total", ".shop.carton", ".shop.scart"]]);
+
+        assert_eq!(woods.store.search("basket"), vec![".shop.cart"]);
+        assert_eq!(woods.store.search("BASK"), vec![".shop.cart"]);
+    }
+
+    #[test]
+    fn search_ranks_fuzzy_matches_last() {
+        let woods = forest();
+        assert_eq!(ranks(woods.store.search("cat"), &[2, 4]), vec![
+            vec![".shop.catalog", ".shop.catalog.art"],
+            vec![".shop.cart", ".shop.carton", ".shop.scart", ".workshop.cart"],
+        ]);
+        assert_eq!(woods.store.search("chkt"), vec![".shop.checkout"]);
+        assert!(woods.store.search("  ").is_empty());
+        assert!(woods.store.search("xyz").is_empty());
+    }
+}
@@ -15,24 +15,116 @@ use amethyst:: {
 };
 
 
-pub struct ShowState {
-    pub light_angle: f32,
-    pub light_color: renderer::palette::Srgb,
-    pub ambient_light: bool,
-    pub point_light: bool,
-    pub directional_light: bool,
-    pub camera_angle: f32,
+// Colors of the scene, channels range over 0..1
+#[derive(Clone)]
+pub struct Theme {
+    pub clear: [f32; 4],
+    pub node: [f32; 4],
+    pub edge: [f32; 4],
+    pub link: [f32; 4],
+    pub label: [f32; 4],
+    pub title: [f32; 4],
+    pub highlight: [f32; 4],
 }
 
-impl Default for ShowState {
+impl Theme {
+    pub const NAMES: &'static [&'static str] = &["dark", "light"];
+
+    pub fn dark() -> Theme {
+        Theme {
+            clear: [0.01, 0.03, 0.03, 1.0],
+            node: [1.0, 1.0, 1.0, 0.5],
+            edge: [0.78, 0.78, 0.78, 1.0],
+            link: [0.9, 0.55, 0.08, 1.0],
+            label: [1.0, 0.04, 0.04, 1.],
+            title: [0.9, 0.9, 0.9, 1.],
+            highlight: [0.08, 0.9, 0.47, 1.],
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            clear: [0.92, 0.93, 0.9, 1.0],
+            node: [0.2, 0.3, 0.5, 1.0],
+            edge: [0.25, 0.25, 0.25, 1.0],
+            link: [0.85, 0.4, 0.05, 1.0],
+            label: [0.6, 0.05, 0.05, 1.],
+            title: [0.1, 0.1, 0.1, 1.],
+            highlight: [0.05, 0.6, 0.3, 1.],
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Theme {
     fn default() -> Self {
-        ShowState {
-            light_angle: 0.0,
-            light_color: renderer::palette::Srgb::new(1.0, 1.0, 1.0),
-            ambient_light: true,
-            point_light: true,
-            directional_light: true,
-            camera_angle: 0.0
+        Theme::dark()
+    }
+}
+
+// Edges and state of the force layout simulation
+#[derive(Default)]
+pub struct ForceGraph {
+    pub enabled: bool,
+    pub levels: bool,
+    pub edges: Vec<(Entity, Entity)>,
+    pub links: Vec<(Entity, Entity)>,
+    pub paused: bool,
+    // Cools down to zero as the layout converges, scales every move
+    pub heat: f32,
+}
+
+impl ForceGraph {
+    pub fn reheat(&mut self) {
+        self.heat = 1.0;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.enabled && !self.paused && self.heat > 0.0
+    }
+}
+
+// Node picked by the user, by path
+#[derive(Default)]
+pub struct Selection {
+    pub path: Option<String>,
+    pub entity: Option<Entity>,
+    // Text showing the selected node
+    pub panel: Option<Entity>,
+    // Set until the selection is shown
+    pub changed: bool,
+    // Set when the selected node is clicked again, until it is collapsed or expanded
+    pub toggle: bool,
+}
+
+impl Selection {
+    pub fn select(&mut self, path: Option<String>, entity: Option<Entity>) {
+        if self.path != path || self.entity != entity {
+            self.path = path;
+            self.entity = entity;
+            self.changed = true;
         }
     }
 }
+
+// Nodes found by the search box
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    // Typed characters go to the query while set
+    pub typing: bool,
+    // Paths matching the query, best first, the camera flies to the current one
+    pub matches: Vec<String>,
+    pub current: usize,
+    // Entities of the shown matches, highlighted every frame
+    pub entities: Vec<Entity>,
+    // Text showing the query
+    pub panel: Option<Entity>,
+}
@@ -1,5 +1,9 @@
 use crate::node::*;
 use crate::tree::*;
+use crate::config::{LayoutConfig, LayoutKind};
+use crate::watch::Reload;
+use crate::diff::DiffStatus;
+use crate::layout::{Layout, Placement, Point, NODE_RADIUS};
 use std::f32::consts::{PI, FRAC_PI_2};
 use serde_json::Value;
 use amethyst:: {
@@ -16,11 +20,16 @@ use amethyst:: {
     ui::{self, get_default_font, FontHandle, TtfFormat, FontAsset, UiText, UiTransform, Anchor, UiLabelBuilder, UiLabelBuilderResources},
     utils::{self, scene},
     window,
+    winit::{Event, WindowEvent},
 };
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use log::{info, warn};
 
 use crate::component::*;
+use crate::resource::{Theme, ForceGraph, Selection, Search};
 
 
 pub type GodsPrefabData = scene::BasicScenePrefab<(Vec<Position>, Vec<Normal>, Vec<TexCoord>)>;
@@ -30,14 +39,31 @@ pub type GodsPrefabData = scene::BasicScenePrefab<(Vec<Position>, Vec<Normal>, V
 pub struct Loading {
     progress: assets::ProgressCounter,
     prefab: Option<assets::Handle<assets::Prefab<GodsPrefabData>>>,
-    wood: Option<Value>,
+    woods: Option<Forest>,
+    layout: Option<LayoutConfig>,
+    collapsed: HashSet<String>,
+    focus: Option<String>,
+    reload: Option<Receiver<Reload>>,
+    statuses: HashMap<String, DiffStatus>,
     font: Option<FontHandle>
 }
 
 impl Loading {
-    pub fn new(raw: &Value) -> Loading {
+    pub fn new(
+        woods: Forest,
+        layout: LayoutConfig,
+        collapsed: HashSet<String>,
+        focus: Option<String>,
+        reload: Option<Receiver<Reload>>,
+        statuses: HashMap<String, DiffStatus>,
+    ) -> Loading {
         let mut state = Loading::default();
-        state.wood = Some(raw.clone());
+        state.woods = Some(woods);
+        state.layout = Some(layout);
+        state.collapsed = collapsed;
+        state.focus = focus;
+        state.reload = reload;
+        state.statuses = statuses;
         state
     }
 }
@@ -76,12 +102,15 @@ impl SimpleState for Loading {
                 if let Some(entity) = data.world.exec(|finder: ui::UiFinder<'_>| finder.find("loading")) {
                     let _ = data.world.delete_entity(entity);
                 }
-                let raw = self.wood.as_ref().unwrap();
-                let mut woods = Forest::new();
-                println!("Adding new wood");
-                woods.add_wood(&raw);
+                let woods = self.woods.take().unwrap();
+                let layout = self.layout.take().unwrap();
                 let font = self.font.clone().unwrap().clone();
-                Trans::Switch(Box::new(Show { scene: self.prefab.as_ref().unwrap().clone(), woods, font }))
+                let scene = self.prefab.as_ref().unwrap().clone();
+                let collapsed = std::mem::replace(&mut self.collapsed, HashSet::new());
+                let mut show = Show::new(scene, woods, layout, collapsed, font, self.reload.take());
+                show.focus = self.focus.take();
+                show.statuses = std::mem::replace(&mut self.statuses, HashMap::new());
+                Trans::Switch(Box::new(show))
             }
             assets::Completion::Loading => Trans::None
         }
@@ -91,143 +120,114 @@ impl SimpleState for Loading {
 pub struct Show {
     scene: assets::Handle<assets::Prefab<GodsPrefabData>>,
     woods: Forest,
+    layout: LayoutConfig,
+    // Paths of the collapsed nodes, kept across reloads
+    collapsed: HashSet<String>,
+    // Search to fly to once the nodes are created
+    focus: Option<String>,
     font: FontHandle,
+    reload: Option<Receiver<Reload>>,
+    mesh: Option<assets::Handle<renderer::Mesh>>,
+    mtl: Option<assets::Handle<renderer::Material>>,
+    // Changes of the nodes of a diff by path, and their materials
+    statuses: HashMap<String, DiffStatus>,
+    status_mtls: HashMap<DiffStatus, assets::Handle<renderer::Material>>,
+    // Node and label entities by node path
+    nodes: HashMap<String, (Entity, Entity)>,
+    titles: Vec<(Entity, Entity)>,
+    lines: Option<Entity>,
+    camera: Option<Entity>,
+    // Box around the last placement
+    bounds: Option<(Point, Point)>,
+    // Paths the view was re-rooted on, the last one is shown
+    roots: Vec<String>,
+    view: Option<Forest>,
+    breadcrumbs: Option<Entity>,
 }
 
-impl SimpleState for Show {
-    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
-        // data.world.create_entity().with(self.scene.clone()).build();
-        // data.world.create_entity().with(self.scene.clone()).build();
-        let w = data.world;
-
-        // load assets
-        let (mesh, albedo) = {
-            let mesh = w.exec(|loader: assets::AssetLoaderSystemData<'_, renderer::Mesh>| {
-                loader.load_from_data(
-                    shape::Shape::Sphere(32, 32)
-                        .generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(None)
-                        .into(),
-                    (),
-                )
-            });
-            let albedo = w.exec(|loader: assets::AssetLoaderSystemData<'_, renderer::Texture>| {
-                loader.load_from_data(
-                    rendy::texture::palette::load_from_linear_rgba(palette::LinSrgba::new(1.0, 1.0, 1.0, 0.5)).into(),
-                    (),
-                )
-            });
-
-            (mesh, albedo)
-        };
-
-        { 
-            // Add lights
-            let mut light1_transform = core::Transform::default();
-            light1_transform.set_translation_xyz(6.0, -6.0, -6.0);
-           
-
-            let light1: light::Light = light::PointLight {
-                intensity: 5.0,
-                color: palette::Srgb::new(0.0, 0.3, 0.7),
-                ..light::PointLight::default()
-            }.into();
-            w.create_entity()
-                .with(light1)
-                .with(light1_transform)
-                .build();
-        }
-
-        {
-            // Add camera
-            let mut transform = core::Transform::default();
-            transform.set_translation_xyz(0.0, 10.0, -50.0);
-            transform.prepend_rotation_y_axis(PI);
-            // transform.prepend_rotation_y_axis(PI * 0.03);
-            transform.prepend_rotation_x_axis(PI * 0.08);
-            // transform.prepend_rotation_z_axis(PI * 0.03);
-            let (width, height) = {
-                let dim = w.read_resource::<window::ScreenDimensions>();
-                (dim.width(), dim.height())
-            };
-            let entity = w.create_entity()
-                .with(camera::Camera::standard_3d(width, height))
-                .with(transform)
-                .build();
-            w.insert(renderer::ActiveCamera {
-                entity: Some(entity),
-            });
-        }
-
-        // Add debug lines
-        // Setup debug lines as a resource
-        w.insert(DebugLines::new());
-        // Configure width of lines. Optional step
-        w.insert(DebugLinesParams { line_width: 2.0 });
-
-        let mut debug_lines_component = DebugLinesComponent::with_capacity(100);
-
-        macro_rules! draw_line {
-            ($begin: expr, $end: expr) => {
-                {
-                    debug_lines_component.add_direction(
-                        $begin,
-                        $end,
-                        palette::Srgba::new(200.0, 200.0, 200.23, 1.0),
-                    );
-                }
-            }
+impl Show {
+    pub fn new(
+        scene: assets::Handle<assets::Prefab<GodsPrefabData>>,
+        woods: Forest,
+        layout: LayoutConfig,
+        collapsed: HashSet<String>,
+        font: FontHandle,
+        reload: Option<Receiver<Reload>>,
+    ) -> Show {
+        Show {
+            scene,
+            woods,
+            layout,
+            collapsed,
+            focus: None,
+            font,
+            reload,
+            mesh: None,
+            mtl: None,
+            statuses: HashMap::new(),
+            status_mtls: HashMap::new(),
+            nodes: HashMap::new(),
+            titles: Vec::new(),
+            lines: None,
+            camera: None,
+            bounds: None,
+            roots: Vec::new(),
+            view: None,
+            breadcrumbs: None,
         }
+    }
 
-        macro_rules! add_rotated_circle {
-            ($center: expr, $radius: expr, $points: expr, $rotation: expr, $color: expr) => {
-                {
-                    let mut prev = None;
-
-                    for i in 0..=$points {
-                        let a = PI * 2.0 / ($points as f32) * (i as f32);
-                        let x = $radius * a.cos();
-                        let y = $radius * a.sin();
-                        let point = Vector3::new(x, y, 0.0);
-                        let point = $center + $rotation * point;
-
-                        if let Some(prev) = prev {
-                            debug_lines_component.add_line(prev, point, $color);
-                        }
-
-                        prev = Some(point);
-                    }
-
-                }
-            }
-        }
+    fn create_node(&self, w: &mut World, node: Arc<Node>, pos: Point) -> (Entity, Entity) {
+        let theme = w.read_resource::<Theme>().clone();
+        let (path, text) = {
+            let state = node.read().unwrap();
+            (state.read_path(), Show::label_text(&state))
+        };
+        let mtl = self.statuses.get(&path).and_then(|status| self.status_mtls.get(status)).or(self.mtl.as_ref()).cloned().unwrap();
+        let mut transform = core::Transform::default();
+        transform.set_translation_xyz(pos.x, pos.y, pos.z);
+
+        // Create godswood node
+        let parent = w.create_entity()
+            .with(transform)
+            .with(self.mesh.clone().unwrap())
+            .with(mtl)
+            .with(GodsNode { node })
+            .build();
 
+        // Create UI display
+        let label = w.create_entity()
+            .with(NodeLabel { node: parent, size: 24.0, title: false })
+            .with(UiTransform::new(format!("node{}", path), Anchor::BottomLeft, Anchor::Middle, 0., 0., 0., 200., 50.))
+            .with(UiText::new(self.font.clone(), text, theme.label, 24.0))
+            .build();
+        (parent, label)
+    }
 
-        macro_rules! draw_circle {
-            ($center: expr, $radius: expr) => {
-                {
-                    debug_lines_component.add_rotated_circle(
-                    // add_rotated_circle!(
-                        $center,
-                        $radius,
-                        100,
-                        UnitQuaternion::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2),
-                        palette::Srgba::new(200.0, 200.0, 200.23, 1.0)
-                    );
-                }
-            }
+    // Collapsed nodes carry a badge with the number of hidden nodes
+    fn label_text(node: &NodeProto) -> String {
+        if node.collapsed {
+            format!("node{} +{}", node.name, node.descendants())
+        } else {
+            format!("node{}", node.name)
         }
+    }
 
-
+    fn load_material(w: &mut World, color: [f32; 4]) -> assets::Handle<renderer::Material> {
         let mat_defaults = w.read_resource::<renderer::MaterialDefaults>().0.clone();
 
         let roughness = 1.0f32 * (3.0 / 4.0f32);
         let metallic = 1.0f32 * (2.0 / 4.0f32);
 
-        let mtl = w.exec(
+        w.exec(
             |(mtl_loader, tex_loader): (
                 assets::AssetLoaderSystemData<'_, renderer::Material>,
                 assets::AssetLoaderSystemData<'_, renderer::Texture>,
             )| {
+                let albedo = tex_loader.load_from_data(
+                    rendy::texture::palette::load_from_linear_rgba(palette::LinSrgba::new(color[0], color[1], color[2], color[3])).into(),
+                    (),
+                );
                 let metallic_roughness = tex_loader.load_from_data(
                     rendy::texture::palette::load_from_linear_rgba(palette::LinSrgba::new(0.0, roughness, metallic, 0.0))
                         .into(),
@@ -236,139 +236,743 @@ impl SimpleState for Show {
 
                 mtl_loader.load_from_data(
                     renderer::Material {
-                        albedo: albedo.clone(),
+                        albedo,
                         metallic_roughness,
                         ..mat_defaults.clone()
                     },
                     (),
                 )
             },
-        );
-
-        macro_rules! pos {
-            ($x: expr, $y: expr, $z: expr) => {
-                {
-                    let mut pos = core::Transform::default();
-                    pos.set_translation_xyz($x, $y, $z);
-                    pos
+        )
+    }
+
+    fn create_title(&self, w: &mut World, pos: Point, title: String) -> (Entity, Entity) {
+        let theme = w.read_resource::<Theme>().clone();
+        let mut transform = core::Transform::default();
+        transform.set_translation_xyz(pos.x, pos.y, pos.z);
+
+        let anchor = w.create_entity()
+            .with(transform)
+            .build();
+
+        let label = w.create_entity()
+            .with(NodeLabel { node: anchor, size: 36.0, title: true })
+            .with(UiTransform::new(format!("wood_{}", title), Anchor::BottomLeft, Anchor::Middle, 0., 0., 0., 400., 60.))
+            .with(UiText::new(self.font.clone(), title, theme.title, 36.0))
+            .build();
+        (anchor, label)
+    }
+
+    // The woods, or the subtree they were re-rooted on
+    fn shown(&self) -> &Forest {
+        self.view.as_ref().unwrap_or(&self.woods)
+    }
+
+    // Arrange the shown woods for the current layout and place their visible nodes
+    fn place(&self) -> Placement {
+        let woods = self.shown();
+        woods.collapse(&self.collapsed);
+        woods.arrange(&self.layout);
+        self.layout.engine().place(woods)
+    }
+
+    // Show the subtree of the selected node alone, the previous root goes on the back stack
+    fn focus_subtree(&mut self, w: &mut World) {
+        let path = match w.read_resource::<Selection>().path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        if self.roots.last() == Some(&path) {
+            return;
+        }
+        self.roots.push(path);
+        self.set_root(w);
+        self.fit(w, None);
+    }
+
+    // Return to the previous root
+    fn focus_back(&mut self, w: &mut World) {
+        if self.roots.pop().is_some() {
+            self.set_root(w);
+            self.fit(w, None);
+        }
+    }
+
+    // Lay out the top of the back stack as the only wood, or all woods once the stack is empty.
+    // Roots which are gone from the woods are dropped.
+    fn set_root(&mut self, w: &mut World) {
+        self.view = None;
+        while let Some(path) = self.roots.last().cloned() {
+            match self.woods.subtree(&path) {
+                Some(view) => {
+                    self.view = Some(view);
+                    break;
+                },
+                None => {
+                    self.roots.pop();
                 }
             }
         }
 
-        macro_rules! create_node {
-            ($node: expr, $pos: expr, $id: expr, $name: expr) => {
-                {
-                    // Create godswood node
-                    let parent = w.create_entity()
-                        .with($pos.clone())
-                        .with(mesh.clone())
-                        .with(mtl.clone())
-                        .with(GodsNode { node: $node })
-                        .build();
-
-                    // Create UI display
-                    w.create_entity()
-                        .with(core::Parent { entity: parent })
-                        // .with($pos)
-                        .with(UiTransform::new(format!("node{}", $id), Anchor::Middle, Anchor::Middle, 800., 580., 0., 200., 50.))
-                        .with(UiText::new(self.font.clone(), format!("node{}", $name), [255., 10., 10., 1.], 50.0))
-                        .build();
-                    /*
-                    UiLabelBuilder::new(format!("node{}", $name))
-                        .with_id(format!("node{}", $id))
-                        .with_anchor(Anchor::Middle)
-                        .with_position(0., 0.)
-                        .with_font(self.font.clone())
-                        .with_font_size(50.)
-                        .with_text_color([255., 10., 10., 1.])
-                        .with_parent(parent)
-                        .build_from_world(w);
-                    */
+        // Breadcrumbs from the wood root down to the current root
+        let crumbs = match self.roots.last() {
+            Some(path) => GodsnodePath::split(path).join(" > "),
+            None => String::new(),
+        };
+        if let Some(entity) = self.breadcrumbs {
+            if let Some(ui_text) = w.write_storage::<UiText>().get_mut(entity) {
+                ui_text.text = crumbs;
+            }
+        }
+        let placement = self.place();
+        self.sync(w, &placement);
+    }
 
+    // Perspective camera for the cone, orthographic camera for flat layouts
+    fn create_camera(&mut self, w: &mut World, placement: &Placement) {
+        if let Some(entity) = self.camera.take() {
+            let _ = w.delete_entity(entity);
+        }
+        let (width, height) = {
+            let dim = w.read_resource::<window::ScreenDimensions>();
+            (dim.width(), dim.height())
+        };
+        let (camera, orbit) = if self.layout.kind.is_flat() {
+            Show::flat_camera(placement, width, height)
+        } else {
+            // Behind the woods, looking slightly down on them
+            let orbit = OrbitCamera::new(Vector3::new(0.0, 0.0, 0.0), PI, 0.2, 51.0, false);
+            (camera::Camera::standard_3d(width, height), orbit)
+        };
+        let mut orbit = orbit;
+        if let Some((min, max)) = placement.bounds() {
+            orbit.fit(Vector3::new(min.x, min.y, min.z), Vector3::new(max.x, max.y, max.z), width / height);
+            orbit.snap();
+        }
+        let mut transform = core::Transform::default();
+        transform.set_translation(orbit.eye());
+        transform.face_towards(orbit.target, Vector3::y());
+        let entity = w.create_entity()
+            .with(camera)
+            .with(orbit)
+            .with(transform)
+            .build();
+        w.insert(renderer::ActiveCamera {
+            entity: Some(entity),
+        });
+        self.camera = Some(entity);
+    }
+
+    // Move the loaded woods over to another layout, nodes glide to their new places
+    fn switch_layout(&mut self, w: &mut World, kind: LayoutKind) {
+        info!("Switching to the {:?} layout", kind);
+        let flat = self.layout.kind.is_flat();
+        self.layout.kind = kind;
+        let placement = self.place();
+        if flat || kind.is_flat() {
+            self.create_camera(w, &placement);
+        }
+        self.sync(w, &placement);
+    }
+
+    // Collapse the selected node or expand it again, the layout is recomputed for the visible nodes
+    fn toggle_collapse(&mut self, w: &mut World) {
+        let path = match w.read_resource::<Selection>().path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let node = self.nodes.get(&path)
+            .and_then(|(entity, _)| w.read_storage::<GodsNode>().get(*entity).map(|node| node.node.clone()));
+        let hidden = match node {
+            Some(node) => node.read().unwrap().descendants(),
+            None => return,
+        };
+        if hidden == 0 {
+            return;
+        }
+        if self.collapsed.remove(&path) {
+            info!("Expanded {}", path);
+        } else {
+            info!("Collapsed {}, hiding {} nodes", path, hidden);
+            self.collapsed.insert(path);
+        }
+        let placement = self.place();
+        self.sync(w, &placement);
+    }
+
+    // Run the query again over the current woods, the current match stays when it is still found
+    fn refresh_search(&self, w: &mut World) {
+        let (text, panel) = {
+            let mut fetched = w.write_resource::<Search>();
+            let search: &mut Search = &mut fetched;
+            let current = search.matches.get(search.current).cloned();
+            // Only the current root and the nodes below it are shown
+            let root = self.roots.last();
+            search.matches = self.woods.store.search(&search.query).into_iter()
+                .filter(|path| match root {
+                    Some(root) => path == root || path.starts_with(&format!("{}.", root)),
+                    None => true,
+                })
+                .collect();
+            search.current = current
+                .and_then(|current| search.matches.iter().position(|path| *path == current))
+                .unwrap_or(0);
+            search.entities = search.matches.iter()
+                .filter_map(|path| self.nodes.get(path))
+                .map(|(entity, _)| *entity)
+                .collect();
+
+            let text = if search.typing {
+                format!("/{}", search.query)
+            } else if search.query.is_empty() {
+                String::new()
+            } else if search.matches.is_empty() {
+                format!("/{}  no match", search.query)
+            } else {
+                format!("/{}  {} of {}", search.query, search.current + 1, search.matches.len())
+            };
+            (text, search.panel)
+        };
+        if let Some(panel) = panel {
+            if let Some(ui_text) = w.write_storage::<UiText>().get_mut(panel) {
+                ui_text.text = text;
+            }
+        }
+    }
+
+    // Take typed characters into the query, enter flies to the best match and escape clears the search
+    fn type_search(&mut self, w: &mut World, c: char) {
+        {
+            let mut search = w.write_resource::<Search>();
+            match c {
+                '\r' | '\n' => search.typing = false,
+                '\u{1b}' => {
+                    search.typing = false;
+                    search.query.clear();
+                },
+                '\u{8}' | '\u{7f}' => {
+                    search.query.pop();
+                },
+                // The key opening the search box
+                '/' if search.query.is_empty() => {},
+                c if !c.is_control() => search.query.push(c),
+                _ => return,
+            }
+        }
+        self.refresh_search(w);
+        if !w.read_resource::<Search>().typing {
+            self.fly_to_match(w);
+        }
+    }
+
+    // Move to the next match, or the previous one for a negative step
+    fn cycle_search(&mut self, w: &mut World, step: isize) {
+        {
+            let mut search = w.write_resource::<Search>();
+            let count = search.matches.len() as isize;
+            if count == 0 {
+                return;
+            }
+            search.current = ((search.current as isize + step).rem_euclid(count)) as usize;
+        }
+        self.fly_to_match(w);
+    }
+
+    // Entity of the node with the given path, collapsed nodes hiding it are expanded first
+    fn reveal(&mut self, w: &mut World, path: &str) -> Option<Entity> {
+        if !self.nodes.contains_key(path) {
+            let before = self.collapsed.len();
+            self.collapsed.retain(|collapsed| !path.starts_with(&format!("{}.", collapsed)));
+            if self.collapsed.len() != before {
+                let placement = self.place();
+                self.sync(w, &placement);
+            }
+        }
+        self.nodes.get(path).map(|(entity, _)| *entity)
+    }
+
+    // Move the selection to the parent, first child, next or previous sibling or root of the
+    // selected node, starting from the first root. The camera turns towards the new node.
+    fn navigate(&mut self, w: &mut World, action: &str) {
+        let current = w.read_resource::<Selection>().entity
+            .and_then(|entity| w.read_storage::<GodsNode>().get(entity).map(|node| node.node.clone()));
+        let roots: Vec<Arc<Node>> = self.shown().ordered().iter()
+            .filter_map(|wood| wood.read().unwrap().wood.get_root().upgrade())
+            .collect();
+
+        let target = match current {
+            None => roots.first().cloned(),
+            Some(node) => {
+                let state = node.read().unwrap();
+                let parent = state.get_parents().first().and_then(|parent| parent.upgrade());
+                match action {
+                    "nav_parent" => parent,
+                    "nav_child" => state.get_children().iter().filter_map(|child| child.upgrade()).next(),
+                    "nav_next" | "nav_previous" => {
+                        // Roots are siblings of each other
+                        let siblings: Vec<Arc<Node>> = match parent {
+                            Some(parent) => parent.read().unwrap().get_children().iter().filter_map(|child| child.upgrade()).collect(),
+                            None => roots,
+                        };
+                        let index = siblings.iter().position(|sibling| Arc::ptr_eq(sibling, &node));
+                        let index = match (index, action) {
+                            (Some(index), "nav_next") => index + 1,
+                            (Some(index), _) if index > 0 => index - 1,
+                            _ => return,
+                        };
+                        siblings.get(index).cloned()
+                    },
+                    _ => {
+                        // Up to the topmost node shown
+                        let mut root = node.clone();
+                        let mut parent = parent;
+                        while let Some(next) = parent.filter(|parent| self.nodes.contains_key(&parent.read().unwrap().read_path())) {
+                            parent = next.read().unwrap().get_parents().first().and_then(|parent| parent.upgrade());
+                            root = next;
+                        }
+                        Some(root)
+                    },
                 }
             }
+        };
+
+        let path = match target {
+            Some(target) => target.read().unwrap().read_path(),
+            None => return,
+        };
+        let entity = match self.reveal(w, &path) {
+            Some(entity) => entity,
+            None => return,
+        };
+        w.write_resource::<Selection>().select(Some(path), Some(entity));
+        self.follow(w, entity);
+    }
+
+    // Turn the camera towards a node without changing its distance, towards where the node is going
+    // when it is still moving
+    fn follow(&self, w: &mut World, entity: Entity) {
+        let camera = match self.camera {
+            Some(camera) => camera,
+            None => return,
+        };
+        let position = w.read_storage::<Motion>().get(entity).map(|motion| motion.target)
+            .or_else(|| w.read_storage::<core::Transform>().get(entity).map(|transform| *transform.translation()));
+        if let (Some(p), Some(orbit)) = (position, w.write_storage::<OrbitCamera>().get_mut(camera)) {
+            orbit.goal_target = if orbit.flat { Vector3::new(p.x, p.y, 0.0) } else { p };
         }
+    }
+
+    // Select the current match and fly the camera to it, collapsed nodes hiding it are expanded
+    fn fly_to_match(&mut self, w: &mut World) {
+        let path = {
+            let search = w.read_resource::<Search>();
+            match search.matches.get(search.current) {
+                Some(path) => path.clone(),
+                None => return,
+            }
+        };
+        let entity = match self.reveal(w, &path) {
+            Some(entity) => entity,
+            None => return,
+        };
+        info!("Flying to {}", path);
+        w.write_resource::<Selection>().select(Some(path.clone()), Some(entity));
+        self.fit(w, Some(path.as_str()));
+        self.refresh_search(w);
+    }
 
-        let woods = self.woods.woods.read().unwrap();
-        let mut nodes = VecDeque::new();
-        for wood in woods.values() {
-            let wood = wood.read().unwrap();
-            let gap = wood.base_gap * -1.0f32;
-            let direction = Vector3::new(0.0, gap, 0.0);
+    // Orthographic camera looking down -z at the x-y plane, the orbit is fitted afterwards
+    fn flat_camera(placement: &Placement, width: f32, height: f32) -> (camera::Camera, OrbitCamera) {
+        let half_height = match placement.bounds() {
+            Some((min, max)) => ((max.y - min.y) / 2.0).max((max.x - min.x) / 2.0 / (width / height)).max(1.0),
+            None => 10.0,
+        };
+        let half_width = half_height * width / height;
+        let camera = camera::Camera::orthographic(-half_width, half_width, -half_height, half_height, 0.1, ORBIT_FLAT_DISTANCE * 2.0);
+        let mut orbit = OrbitCamera::new(Vector3::new(0.0, 0.0, 0.0), 0.0, 0.0, ORBIT_FLAT_DISTANCE, true);
+        orbit.half_height = half_height;
+        (camera, orbit)
+    }
 
-            nodes.push_back(((0.0, 0.0, 0.0), wood.wood.get_root(), 1));
+    // Frame the nodes whose paths pass the filter, or everything placed when the
+    // woods are where the layout put them
+    fn fit(&self, w: &mut World, filter: Option<&str>) {
+        let camera = match self.camera {
+            Some(camera) => camera,
+            None => return,
+        };
+        let aspect = {
+            let dim = w.read_resource::<window::ScreenDimensions>();
+            dim.width() / dim.height()
+        };
 
-            loop {
-                let node = nodes.pop_front();
-                if node.is_none() {
-                    break;
+        let bounds = match (filter, self.bounds) {
+            (None, Some(bounds)) if !self.is_force() => Some(bounds),
+            _ => {
+                let transforms = w.read_storage::<core::Transform>();
+                let mut bounds: Option<(Point, Point)> = None;
+                for (path, (entity, _)) in self.nodes.iter() {
+                    let inside = match filter {
+                        Some(root) => path == root || path.starts_with(&format!("{}.", root)),
+                        None => true,
+                    };
+                    if let (true, Some(transform)) = (inside, transforms.get(*entity)) {
+                        let p = transform.translation();
+                        bounds = Some(match bounds {
+                            Some((min, max)) => (
+                                Point::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                                Point::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                            ),
+                            None => (Point::new(p.x, p.y, p.z), Point::new(p.x, p.y, p.z)),
+                        });
+                    }
                 }
+                bounds.map(|(min, max)| (
+                    Point::new(min.x - NODE_RADIUS, min.y - NODE_RADIUS, min.z - NODE_RADIUS),
+                    Point::new(max.x + NODE_RADIUS, max.y + NODE_RADIUS, max.z + NODE_RADIUS),
+                ))
+            }
+        };
 
-                let ((x, y, z), node, depth) = node.unwrap();
-                let node_arc = node.upgrade().unwrap();
-                let scale = wood.scales.get(&depth).unwrap() * wood.base_scale;
-                let node = node_arc.read().unwrap();
-                create_node!(node_arc.clone(), pos!(x, y, z), node.id, node.name.clone());
+        if let (Some((min, max)), Some(orbit)) = (bounds, w.write_storage::<OrbitCamera>().get_mut(camera)) {
+            orbit.fit(Vector3::new(min.x, min.y, min.z), Vector3::new(max.x, max.y, max.z), aspect);
+        }
+    }
 
-                let children = node.get_children();
-                let size = children.len();
-                if size == 0 {
-                    continue;
-                } else if size == 1 {
-                    draw_line!(Point3::new(x, y, z), Vector3::new(0.0, -wood.base_gap, 0.0));
-                    nodes.push_back(((x, y - wood.base_gap, z), children[0].clone(), depth + 1));
+    // Draw edges, rings and cross-links, replacing the previous ones
+    fn draw_lines(&mut self, w: &mut World, placement: &Placement) {
+        let theme = w.read_resource::<Theme>().clone();
+        let color = |c: [f32; 4]| palette::Srgba::new(c[0], c[1], c[2], c[3]);
+        let mut lines = DebugLinesComponent::with_capacity(100);
+
+        let point = |p: &Point| Point3::new(p.x, p.y, p.z);
+
+        if self.is_force() {
+            // Edges move with the nodes, the force system draws them each frame
+            if let Some(entity) = self.lines.take() {
+                let _ = w.delete_entity(entity);
+            }
+            return;
+        }
+
+        for (begin, end) in placement.edges.iter() {
+            lines.add_line(point(begin), point(end), color(theme.edge));
+        }
+        for (center, radius) in placement.rings.iter() {
+            lines.add_rotated_circle(
+                point(center),
+                *radius,
+                100,
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2),
+                color(theme.edge),
+            );
+        }
+        for (_, node, pos) in placement.nodes.iter() {
+            if !node.read().unwrap().collapsed {
+                continue;
+            }
+            // Badge ring around collapsed nodes, facing the camera
+            if self.layout.kind.is_flat() {
+                lines.add_circle_2d(point(pos), NODE_RADIUS * 1.6, 24, color(theme.link));
+            } else {
+                lines.add_rotated_circle(
+                    point(pos),
+                    NODE_RADIUS * 1.6,
+                    24,
+                    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2),
+                    color(theme.link),
+                );
+            }
+        }
+        for (a, b) in placement.rects.iter() {
+            let corners = [(a.x, a.y), (b.x, a.y), (b.x, b.y), (a.x, b.y), (a.x, a.y)];
+            for pair in corners.windows(2) {
+                lines.add_line(Point3::new(pair[0].0, pair[0].1, a.z), Point3::new(pair[1].0, pair[1].1, a.z), color(theme.edge));
+            }
+        }
+        for (center, radius) in placement.discs.iter() {
+            lines.add_circle_2d(point(center), *radius, 100, color(theme.edge));
+        }
+        for sector in placement.sectors.iter() {
+            let outline = sector.outline(2.0 * PI / 100.0);
+            for pair in outline.windows(2) {
+                lines.add_line(point(&pair[0]), point(&pair[1]), color(theme.edge));
+            }
+        }
+        for (begin, end) in placement.links.iter() {
+            lines.add_line(point(begin), point(end), color(theme.link));
+        }
+
+        if let Some(entity) = self.lines.take() {
+            let _ = w.delete_entity(entity);
+        }
+        self.lines = Some(w.create_entity().with(lines).build());
+    }
+
+    // Spawn new nodes, move kept ones and despawn removed ones to match the placement
+    fn sync(&mut self, w: &mut World, placement: &Placement) {
+        self.bounds = placement.bounds();
+        let mut stale: HashSet<String> = self.nodes.keys().cloned().collect();
+        for (path, node, pos) in placement.nodes.iter() {
+            stale.remove(path);
+            let entity = match self.nodes.get(path) {
+                Some((entity, label)) => {
+                    if let Some(text) = w.write_storage::<UiText>().get_mut(*label) {
+                        text.text = Show::label_text(&node.read().unwrap());
+                    }
+                    *entity
+                },
+                None => {
+                    let entities = self.create_node(w, node.clone(), *pos);
+                    self.nodes.insert(path.clone(), entities);
                     continue;
                 }
+            };
 
-                // draw_line!(Point3::new(x, y, z), direction);
-                draw_circle!(Point3::new(x, y - wood.base_gap, z), scale);
-                // break;
+            let _ = w.write_storage::<GodsNode>().insert(entity, GodsNode { node: node.clone() });
+            if self.is_force() && w.read_storage::<ForceNode>().contains(entity) {
+                // Kept nodes stay where the simulation moved them
+                continue;
+            }
+            let current = w.read_storage::<core::Transform>().get(entity).map(|t| *t.translation());
+            let target = Vector3::new(pos.x, pos.y, pos.z);
+            if current != Some(target) {
+                let _ = w.write_storage::<Motion>().insert(entity, Motion::new(target));
+            }
+        }
 
-                let mut points = Vec::new();
+        {
+            let mut selection = w.write_resource::<Selection>();
+            if selection.path.as_ref().map(|path| stale.contains(path)).unwrap_or(false) {
+                selection.select(None, None);
+            }
+        }
+        for path in stale.iter() {
+            if let Some((node, label)) = self.nodes.remove(path) {
+                let _ = w.delete_entity(label);
+                let _ = w.delete_entity(node);
+            }
+        }
 
-                let angle = 2f32 * PI / size as f32;
-                for i in 0..size {
-                    let angle = angle * i as f32;
-                    let kid_x = x - scale * angle.cos();
-                    let kid_y = y - wood.base_gap;
-                    let kid_z = z - scale * angle.sin();
+        for (anchor, label) in self.titles.drain(..) {
+            let _ = w.delete_entity(label);
+            let _ = w.delete_entity(anchor);
+        }
+        for (pos, title) in placement.titles.iter() {
+            let title = self.create_title(w, *pos, title.clone());
+            self.titles.push(title);
+        }
 
-                    draw_line!(Point3::new(x, y, z), Vector3::new(kid_x - x, kid_y - y, kid_z - z));
-                    points.push((kid_x, kid_y, kid_z));
-                }
+        if self.is_force() {
+            self.sync_force(w, placement);
+        } else if w.has_value::<ForceGraph>() {
+            w.write_storage::<ForceNode>().clear();
+            w.insert(ForceGraph::default());
+        }
+        self.draw_lines(w, placement);
+        self.refresh_search(w);
+    }
+
+    fn is_force(&self) -> bool {
+        self.layout.kind == LayoutKind::Force
+    }
 
-                for node in children.iter() {
-                    nodes.push_back((points.pop().unwrap(), node.clone(), depth + 1));
+    // Hand the nodes and their edges over to the force simulation, it starts hot again
+    fn sync_force(&mut self, w: &mut World, placement: &Placement) {
+        let mut graph = ForceGraph::default();
+        graph.enabled = true;
+        graph.levels = self.layout.levels;
+        graph.reheat();
+
+        let entity = |path: &String| self.nodes.get(path).map(|(entity, _)| *entity);
+        for (path, node, pos) in placement.nodes.iter() {
+            let from = match entity(path) {
+                Some(from) => from,
+                None => continue,
+            };
+            let mut forces = w.write_storage::<ForceNode>();
+            match forces.get_mut(from) {
+                Some(force) => force.level = pos.y,
+                None => {
+                    let _ = forces.insert(from, ForceNode::new(pos.y));
                 }
             }
 
-            /*
-            let nodes = wood.wood.get_nodes_by_depths();
-            let depth = wood.wood.get_depth();
-            let nodes = nodes.read().unwrap();
-            for i in 1..depth + 1 {
-                let items = nodes.get(&i).unwrap();
-                let mut index = 0.0f32;
-                for item in items.iter() {
-                    create_node!(item.upgrade().unwrap().clone(), pos!(3.0 * i as f32, 3.0 * index, 0.0));
-                    index += 1.0;
-                }
+            let node = node.read().unwrap();
+            let ends = |nodes: &Vec<std::sync::Weak<Node>>| -> Vec<Entity> {
+                nodes.iter()
+                    .filter_map(|other| other.upgrade())
+                    .filter_map(|other| entity(&other.read().unwrap().read_path()))
+                    .collect()
+            };
+            for to in ends(node.get_children()) {
+                graph.edges.push((from, to));
+            }
+            for to in ends(node.get_links()) {
+                graph.links.push((from, to));
             }
-            */
         }
+        w.insert(graph);
+    }
+}
+
+impl SimpleState for Show {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        // data.world.create_entity().with(self.scene.clone()).build();
+        // data.world.create_entity().with(self.scene.clone()).build();
+        let w = data.world;
+        let theme = w.read_resource::<Theme>().clone();
+
+        // load assets
+        let mesh = w.exec(|loader: assets::AssetLoaderSystemData<'_, renderer::Mesh>| {
+            loader.load_from_data(
+                shape::Shape::Sphere(32, 32)
+                    .generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(None)
+                    .into(),
+                (),
+            )
+        });
+
+        { 
+            // Add lights
+            let mut light1_transform = core::Transform::default();
+            light1_transform.set_translation_xyz(6.0, -6.0, -6.0);
+           
 
-        // let node = self.woods.store.new_node();
-        // create_node!(node);
-        
-        w.create_entity()
-            .with(debug_lines_component)
+            let light1: light::Light = light::PointLight {
+                intensity: 5.0,
+                color: palette::Srgb::new(0.0, 0.3, 0.7),
+                ..light::PointLight::default()
+            }.into();
+            w.create_entity()
+                .with(light1)
+                .with(light1_transform)
+                .build();
+        }
+
+        let placement = self.place();
+        self.create_camera(w, &placement);
+
+        // Add debug lines
+        // Setup debug lines as a resource
+        w.insert(DebugLines::new());
+        // Configure width of lines. Optional step
+        w.insert(DebugLinesParams { line_width: 2.0 });
+
+        // Side panel describing the selected node
+        let panel = {
+            let mut text = UiText::new(self.font.clone(), String::new(), theme.title, 20.0);
+            text.line_mode = ui::LineMode::Wrap;
+            text.align = Anchor::TopLeft;
+            w.create_entity()
+                .with(UiTransform::new("selection_panel".to_string(), Anchor::TopRight, Anchor::TopRight, -20., -20., 1., 400., 600.))
+                .with(text)
+                .build()
+        };
+        let mut selection = Selection::default();
+        selection.panel = Some(panel);
+        w.insert(selection);
+
+        // Search box, opened with the search action
+        let search_box = w.create_entity()
+            .with(UiTransform::new("search_box".to_string(), Anchor::BottomLeft, Anchor::BottomLeft, 20., 20., 1., 600., 30.))
+            .with(UiText::new(self.font.clone(), String::new(), theme.title, 24.0))
             .build();
+        let mut search = Search::default();
+        search.panel = Some(search_box);
+        w.insert(search);
+
+        // Path of the root the view is focused on
+        self.breadcrumbs = Some(w.create_entity()
+            .with(UiTransform::new("breadcrumbs".to_string(), Anchor::TopMiddle, Anchor::TopMiddle, 0., -20., 1., 800., 30.))
+            .with(UiText::new(self.font.clone(), String::new(), theme.title, 24.0))
+            .build());
+
+        self.mesh = Some(mesh);
+        self.mtl = Some(Show::load_material(w, theme.node));
+        self.status_mtls = DiffStatus::ALL.iter().map(|status| (*status, Show::load_material(w, status.color()))).collect();
+
+        self.sync(w, &placement);
+
+        if let Some(focus) = self.focus.take() {
+            w.write_resource::<Search>().query = focus.clone();
+            self.refresh_search(w);
+            if w.read_resource::<Search>().matches.is_empty() {
+                warn!("No node matches {}", focus);
+            } else {
+                self.fly_to_match(w);
+            }
+        }
+    }
 
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        // Only the latest reload matters
+        let mut reload = None;
+        if let Some(receiver) = &self.reload {
+            while let Ok(woods) = receiver.try_recv() {
+                reload = Some(woods);
+            }
+        }
+
+        let toggle = std::mem::replace(&mut data.world.write_resource::<Selection>().toggle, false);
+        if toggle {
+            self.toggle_collapse(data.world);
+        }
+
+        match reload {
+            Some(Ok(woods)) => {
+                info!("Woods reloaded");
+                self.woods = woods;
+                if self.roots.is_empty() {
+                    let placement = self.place();
+                    self.sync(data.world, &placement);
+                } else {
+                    self.set_root(data.world);
+                }
+            },
+            Some(Err(e)) => warn!("Failed to reload woods: {}", e),
+            None => {}
+        }
+        Trans::None
     }
 
     fn handle_event(&mut self, data: StateData<'_, GameData<'_, '_>>, event: StateEvent) -> SimpleTrans {
+        // Keys go to the search box while it is open
+        let typing = data.world.read_resource::<Search>().typing;
+        match event {
+            StateEvent::Window(Event::WindowEvent { event: WindowEvent::ReceivedCharacter(c), .. }) if typing => {
+                self.type_search(data.world, c);
+            },
+            StateEvent::Input(input::InputEvent::ActionPressed(action)) if !typing => match action.as_str() {
+                "next_layout" => {
+                    let kind = self.layout.kind.next();
+                    self.switch_layout(data.world, kind);
+                },
+                "toggle_collapse" => self.toggle_collapse(data.world),
+                "fit_all" => self.fit(data.world, None),
+                "fit_subtree" => {
+                    let selected = data.world.read_resource::<Selection>().path.clone();
+                    self.fit(data.world, selected.as_ref().map(|path| path.as_str()));
+                },
+                "search" => {
+                    {
+                        let mut search = data.world.write_resource::<Search>();
+                        search.typing = true;
+                        search.query.clear();
+                    }
+                    self.refresh_search(data.world);
+                },
+                "search_next" => self.cycle_search(data.world, 1),
+                "search_previous" => self.cycle_search(data.world, -1),
+                "focus_subtree" => self.focus_subtree(data.world),
+                "focus_back" => self.focus_back(data.world),
+                "nav_parent" | "nav_child" | "nav_next" | "nav_previous" | "nav_root" => {
+                    self.navigate(data.world, action.as_str());
+                },
+                _ => {}
+            },
+            _ => {}
+        }
         Trans::None
     }
 }
-
-
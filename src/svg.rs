@@ -0,0 +1,192 @@
+use crate::layout::*;
+use crate::diff::DiffStatus;
+use crate::resource::Theme;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt::Write;
+
+// Scene units to svg pixels
+const SCALE: f32 = 20.0;
+const RING_POINTS: usize = 64;
+
+// Orthographic camera, yaw turns around the vertical axis and pitch looks down, in degrees
+#[derive(Clone, Copy, Debug)]
+pub struct Projection {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Projection {
+    pub fn top() -> Projection {
+        Projection { yaw: 0.0, pitch: 90.0 }
+    }
+
+    // "top" | "front" | "side" | "<yaw>,<pitch>"
+    pub fn parse(camera: &str) -> Option<Projection> {
+        match camera {
+            "top" => Some(Projection::top()),
+            "front" => Some(Projection { yaw: 0.0, pitch: 0.0 }),
+            "side" => Some(Projection { yaw: 90.0, pitch: 0.0 }),
+            _ => {
+                let mut angles = camera.split(',').map(|v| v.trim().parse::<f32>());
+                match (angles.next(), angles.next(), angles.next()) {
+                    (Some(Ok(yaw)), Some(Ok(pitch)), None) => Some(Projection { yaw, pitch }),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    // Screen x, screen y pointing down, and depth growing away from the camera
+    pub fn project(&self, p: &Point) -> (f32, f32, f32) {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        let x = p.x * yaw.cos() - p.z * yaw.sin();
+        let z = p.x * yaw.sin() + p.z * yaw.cos();
+        let up = p.y * pitch.cos() + z * pitch.sin();
+        let depth = z * pitch.cos() - p.y * pitch.sin();
+        (x * SCALE, -up * SCALE, depth)
+    }
+}
+
+fn hex(c: [f32; 4]) -> String {
+    let channel = |v: f32| (v.max(0.0).min(1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(c[0]), channel(c[1]), channel(c[2]))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Bounding box of the projected drawing
+struct Bounds {
+    min: (f32, f32),
+    max: (f32, f32),
+}
+
+impl Bounds {
+    fn new() -> Bounds {
+        Bounds {
+            min: (std::f32::MAX, std::f32::MAX),
+            max: (std::f32::MIN, std::f32::MIN),
+        }
+    }
+
+    fn add(&mut self, (x, y): (f32, f32), margin: f32) {
+        self.min = (self.min.0.min(x - margin), self.min.1.min(y - margin));
+        self.max = (self.max.0.max(x + margin), self.max.1.max(y + margin));
+    }
+}
+
+// Render the placement of the forest as seen from the projection camera
+// Nodes found in `statuses` are colored by their change, the others by the theme
+pub fn render(placement: &Placement, projection: &Projection, theme: &Theme, statuses: &HashMap<String, DiffStatus>) -> String {
+    let project = |p: &Point| {
+        let (x, y, _) = projection.project(p);
+        (x, y)
+    };
+    let radius = NODE_RADIUS * SCALE;
+    let mut bounds = Bounds::new();
+
+    let rings: Vec<Vec<(f32, f32)>> = placement.rings.iter().map(|(center, r)| {
+        (0..RING_POINTS + 1).map(|i| {
+            let a = 2.0 * PI * i as f32 / RING_POINTS as f32;
+            project(&Point::new(center.x + r * a.cos(), center.y, center.z + r * a.sin()))
+        }).collect()
+    }).collect();
+    let sectors: Vec<Vec<(f32, f32)>> = placement.sectors.iter().map(|sector| {
+        sector.outline(2.0 * PI / RING_POINTS as f32).iter().map(|p| project(p)).collect()
+    }).collect();
+    let rects: Vec<Vec<(f32, f32)>> = placement.rects.iter().map(|(a, b)| {
+        [(a.x, a.y), (b.x, a.y), (b.x, b.y), (a.x, b.y), (a.x, a.y)].iter()
+            .map(|(x, y)| project(&Point::new(*x, *y, a.z)))
+            .collect()
+    }).collect();
+    let discs: Vec<Vec<(f32, f32)>> = placement.discs.iter().map(|(center, r)| {
+        (0..RING_POINTS + 1).map(|i| {
+            let a = 2.0 * PI * i as f32 / RING_POINTS as f32;
+            project(&Point::new(center.x + r * a.cos(), center.y + r * a.sin(), center.z))
+        }).collect()
+    }).collect();
+    let outlines: Vec<&Vec<(f32, f32)>> = rings.iter().chain(sectors.iter()).chain(rects.iter()).chain(discs.iter()).collect();
+    for point in outlines.iter().flat_map(|ring| ring.iter()) {
+        bounds.add(*point, 0.0);
+    }
+
+    // Far nodes first so the near ones cover them
+    let mut nodes: Vec<(f32, f32, f32, String, [f32; 4])> = placement.nodes.iter().map(|(path, node, pos)| {
+        let (x, y, depth) = projection.project(pos);
+        let color = statuses.get(path).map(|status| status.color()).unwrap_or(theme.node);
+        (x, y, depth, node.read().unwrap().name.clone(), color)
+    }).collect();
+    nodes.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+    for (x, y, _, _, _) in nodes.iter() {
+        bounds.add((*x, *y), radius * 4.0);
+    }
+    for (pos, _) in placement.titles.iter() {
+        bounds.add(project(pos), radius * 6.0);
+    }
+    if nodes.is_empty() {
+        bounds.add((0.0, 0.0), radius);
+    }
+
+    let (width, height) = (bounds.max.0 - bounds.min.0, bounds.max.1 - bounds.min.1);
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{:.1} {:.1} {:.1} {:.1}" width="{:.0}" height="{:.0}">"#,
+                     bounds.min.0, bounds.min.1, width, height, width, height);
+    let _ = writeln!(svg, r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+                     bounds.min.0, bounds.min.1, width, height, hex(theme.clear));
+
+    let _ = writeln!(svg, r#"<g fill="none" stroke="{}" stroke-width="1.5">"#, hex(theme.edge));
+    for ring in outlines.iter() {
+        let points: Vec<String> = ring.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+        let _ = writeln!(svg, r#"<polyline points="{}"/>"#, points.join(" "));
+    }
+    for (begin, end) in placement.edges.iter() {
+        let ((x1, y1), (x2, y2)) = (project(begin), project(end));
+        let _ = writeln!(svg, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/>"#, x1, y1, x2, y2);
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(svg, r#"<g fill="none" stroke="{}" stroke-width="1.5" stroke-dasharray="6 4">"#, hex(theme.link));
+    for (begin, end) in placement.links.iter() {
+        let ((x1, y1), (x2, y2)) = (project(begin), project(end));
+        let _ = writeln!(svg, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/>"#, x1, y1, x2, y2);
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(svg, r#"<g font-family="sans-serif" font-size="{:.0}" text-anchor="middle">"#, radius * 0.8);
+    for (x, y, _, name, color) in nodes.iter() {
+        let _ = writeln!(svg, r#"<circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="{}"/>"#, x, y, radius, hex(*color));
+        let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}" fill="{}">{}</text>"#, x, y - radius * 1.4, hex(theme.label), escape(name));
+    }
+    for (pos, title) in placement.titles.iter() {
+        let (x, y) = project(pos);
+        let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}" font-size="{:.0}" fill="{}">{}</text>"#, x, y - radius * 2.0, radius * 1.6, hex(theme.title), escape(title));
+    }
+    let _ = writeln!(svg, "</g>");
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_colors_keep_their_hex() {
+        for name in Theme::NAMES.iter() {
+            let theme = Theme::by_name(name).unwrap();
+            let colors = [theme.node, theme.edge, theme.link, theme.label, theme.title, theme.highlight];
+            for (i, a) in colors.iter().enumerate() {
+                for b in colors[i + 1..].iter() {
+                    assert_ne!(hex(*a), hex(*b), "{} theme colors {:?} and {:?} clamp to the same hex", name, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hex_scales_unit_channels() {
+        assert_eq!(hex([1.0, 0.0, 0.5, 1.0]), "#ff0080");
+    }
+}
@@ -1,5 +1,7 @@
-use crate::resource::ShowState;
-use crate::component::GodsNode;
+use crate::resource::{ForceGraph, Theme, Selection, Search};
+use crate::layout::NODE_RADIUS;
+use crate::component::{GodsNode, Motion, ForceNode, OrbitCamera, NodeLabel, ORBIT_FLAT_DISTANCE};
+use std::collections::HashMap;
 
 use amethyst:: {
     Error,
@@ -7,170 +9,513 @@ use amethyst:: {
     core::{
         self,
         SystemDesc,
-        math::{UnitQuaternion, Vector3, Point3, Vector2},
+        math::{Vector3, Point2, Point3, Vector2},
         transform::Transform
     },
     derive::SystemDesc,
     ecs::prelude::{
-        Entity, Join, Read, ReadStorage, System, Write, WriteStorage, SystemData, ReadExpect
+        Entity, Entities, Join, Read, ReadStorage, System, Write, WriteStorage, SystemData, ReadExpect
     },
     input,
     prelude::*,
     renderer::{self, rendy::mesh::*, palette, debug_drawing::{DebugLines, DebugLinesComponent, DebugLinesParams} },
     ui,
-    utils::{self, scene},
     window
 };
 
 
+// Eases entities with a Motion towards their target
 #[derive(Default, SystemDesc)]
-pub struct ShowSystem {
-    fps_display: Option<Entity>,
+pub struct MotionSystem;
+
+impl<'a> System<'a> for MotionSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Motion>,
+        WriteStorage<'a, Transform>,
+        Read<'a, core::timing::Time>,
+    );
+    fn run(&mut self, (entities, mut motions, mut transforms, time): Self::SystemData) {
+        let mut arrived = Vec::new();
+        for (entity, motion, transform) in (&entities, &motions, &mut transforms).join() {
+            let current = *transform.translation();
+            let delta = motion.target - current;
+            if delta.norm() < 0.01 {
+                transform.set_translation(motion.target);
+                arrived.push(entity);
+            } else {
+                let step = (motion.speed * time.delta_seconds()).min(1.0);
+                transform.set_translation(current + delta * step);
+            }
+        }
+
+        for entity in arrived {
+            motions.remove(entity);
+        }
+    }
+}
+
+
+// Spring length along edges
+const FORCE_REST: f32 = 10.0;
+const FORCE_SPRING: f32 = 2.0;
+const FORCE_REPULSION: f32 = 400.0;
+// Velocity kept per second
+const FORCE_DAMPING: f32 = 0.05;
+// Heat lost per second, the simulation stops once it is cold
+const FORCE_COOLING: f32 = 0.6;
+const FORCE_COLD: f32 = 0.005;
+
+// Force directed layout, springs along edges and links pull nodes together while
+// every pair of nodes pushes apart. Pause resumes where it stopped, freeze drops all
+// momentum and keeps the nodes where they are until resumed.
+#[derive(Default, SystemDesc)]
+pub struct ForceSystem {
+    pause_down: bool,
+    freeze_down: bool,
 }
 
-impl<'a> System<'a> for ShowSystem {
+impl<'a> System<'a> for ForceSystem {
     type SystemData = (
-        WriteStorage<'a, renderer::light::Light>,
+        Entities<'a>,
+        WriteStorage<'a, ForceNode>,
+        WriteStorage<'a, Transform>,
+        ReadStorage<'a, Motion>,
+        Write<'a, ForceGraph>,
         Read<'a, core::timing::Time>,
-        ReadExpect<'a, window::ScreenDimensions>,
-        ReadExpect<'a, renderer::ActiveCamera>,
-        ReadStorage<'a, renderer::Camera>,
-        WriteStorage<'a, ui::UiTransform>,
-        WriteStorage<'a, core::transform::Transform>,
-        Write<'a, ShowState>,
-        Write<'a, GodsNode>,
-        WriteStorage<'a, ui::UiText>,
-        Read<'a, utils::fps_counter::FpsCounter>,
+        Read<'a, input::InputHandler<input::StringBindings>>,
         Write<'a, DebugLines>,
-        Read<'a, input::InputHandler<input::StringBindings>>
+        Read<'a, Theme>,
+        Read<'a, Search>,
     );
-    fn run(&mut self, data: Self::SystemData) {
-        let (mut lights, time, screen, active_camera, camera, mut ui_transforms, mut transforms, mut state, godsnode, mut ui_text, fps_counter, mut dl, input) = data;
-        let t = (time.absolute_time_seconds() as f32).cos();
-        /*
-        let (cam, trans) = {
-            let (cam, trans) = (&camera, &transforms).join().next().unwrap();
-            (cam.clone(), trans.clone())
-        };
-        for (mut ui_trans, _) in (&mut ui_transforms, &mut transforms).join() {
-            let pos = (cam.as_matrix() * trans.global_view_matrix()).transform_vector(&Vector3::new(0.,0.,0.));
-            ui_trans.local_x = pos.x;
-            ui_trans.local_y = pos.y;
+    fn run(&mut self, (entities, mut forces, mut transforms, motions, mut graph, time, input, mut dl, theme, search): Self::SystemData) {
+        if !graph.enabled {
+            return;
         }
-        */
-
-        /*
-        if let Some(camera_entity) = active_camera.entity {
-            let active_camera = camera.get(camera_entity).unwrap();
-            let camera_transform = transforms.get(camera_entity).unwrap().clone();
-            for (mut ui_trans, transform) in (&mut ui_transforms, &transforms).join() {
-                let cam_pos = transform.translation();
-                println!("x {}, y {}", cam_pos.x, cam_pos.y);
-                let pos = (active_camera.as_matrix() * transform.global_view_matrix()).transform_vector(&Vector3::new(0.,0.,0.));
-                println!("x {}, y {}", pos.x, pos.y);
-                ui_trans.local_x = 10.;
-                ui_trans.local_y = 20.;
+
+        // Act on key presses, not on held keys, and not while typing a search
+        let pause_down = input.action_is_down("force_pause").unwrap_or(false) && !search.typing;
+        if pause_down && !self.pause_down {
+            if graph.heat <= 0.0 {
+                graph.paused = false;
+                graph.reheat();
+            } else {
+                graph.paused = !graph.paused;
             }
         }
-        */
-
-        let dimension = Vector2::new(screen.width(), screen.height());
-
-        if let Some(camera_entity) = active_camera.entity {
-            let active_camera = camera.get(camera_entity).unwrap();
-            let camera_transform = transforms.get(camera_entity).unwrap().clone();
-            for (mut ui_trans, mut transform) in (&mut ui_transforms, &mut transforms).join() {
-                let trans = transform.translation().clone();
-                let pos = active_camera.projection().world_to_screen(Point3::new(trans.x, trans.y, trans.z), dimension, &camera_transform);
-                println!("x {}, y {}", pos.x, pos.y);
-                ui_trans.local_x = 800.;
-                ui_trans.local_y = 300.;
-                // transform.set_translation_x(200.);
-                // transform.set_translation_y(200.);
-                dl.draw_line(Point3::new(0.,0.,0.), Point3::new(trans.x, trans.y, trans.z), palette::Srgba::new(200., 0., 0., 1.));
+        self.pause_down = pause_down;
+
+        let freeze_down = input.action_is_down("force_freeze").unwrap_or(false) && !search.typing;
+        if freeze_down && !self.freeze_down {
+            graph.heat = 0.0;
+            for force in (&mut forces).join() {
+                force.velocity = Vector3::new(0.0, 0.0, 0.0);
             }
         }
+        self.freeze_down = freeze_down;
 
-        for (_, mut transform) in (&camera, &mut transforms).join() {
-            if let Some(movement) = input.axis_value("check_up") {
-                transform.prepend_translation_y(
-                    8.0 * time.delta_seconds() * movement as f32,
-                );
+        let positions: HashMap<Entity, Vector3<f32>> = (&entities, &forces, &transforms).join()
+            .map(|(entity, _, transform)| (entity, *transform.translation()))
+            .collect();
 
-                // We make sure the paddle remains in the arena.
-                let y = transform.translation().y;
-                transform.set_translation_y(
-                    y.max(-1000.0 * 0.5).min(1000.0 * 0.5),
-                );
+        if graph.is_running() {
+            let dt = time.delta_seconds().min(1.0 / 30.0);
+            let mut pushes: HashMap<Entity, Vector3<f32>> = positions.keys().map(|entity| (*entity, Vector3::new(0.0, 0.0, 0.0))).collect();
+
+            let nodes: Vec<(&Entity, &Vector3<f32>)> = positions.iter().collect();
+            for (i, &(a, pa)) in nodes.iter().enumerate() {
+                for &(b, pb) in nodes.iter().skip(i + 1) {
+                    let delta = pa - pb;
+                    let distance = delta.norm().max(0.5);
+                    let push = delta / distance * FORCE_REPULSION / (distance * distance);
+                    *pushes.get_mut(a).unwrap() += push;
+                    *pushes.get_mut(b).unwrap() -= push;
+                }
             }
 
-            if let Some(movement) = input.axis_value("check_right") {
-                let angle = time.delta_seconds() * movement as f32;
-                let trans = transform.translation().clone();
+            for (a, b) in graph.edges.iter().chain(graph.links.iter()) {
+                if let (Some(pa), Some(pb)) = (positions.get(a), positions.get(b)) {
+                    let delta = pb - pa;
+                    let distance = delta.norm().max(0.01);
+                    let pull = delta / distance * FORCE_SPRING * (distance - FORCE_REST);
+                    *pushes.get_mut(a).unwrap() += pull;
+                    *pushes.get_mut(b).unwrap() -= pull;
+                }
+            }
 
-                let pos = Point3::new(0., 0., 0.) + UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle) * trans;
+            let damping = FORCE_DAMPING.powf(dt);
+            for (entity, force, transform, _) in (&entities, &mut forces, &mut transforms, !&motions).join() {
+                let mut push = pushes[&entity];
+                if graph.levels {
+                    push.y = 0.0;
+                }
+                force.velocity = (force.velocity + push * dt * graph.heat) * damping;
+                let mut position = *transform.translation() + force.velocity * dt;
+                if graph.levels {
+                    position.y = force.level;
+                }
+                transform.set_translation(position);
+            }
 
-                transform.set_translation_xyz(pos.x, pos.y, pos.z);
-                transform.prepend_rotation_y_axis(angle);
+            graph.heat *= (1.0 - FORCE_COOLING * dt).max(0.0);
+            if graph.heat < FORCE_COLD {
+                graph.heat = 0.0;
             }
         }
 
-        /*
-        dl.draw_direction(
-            [t, 0.0, 0.5].into(),
-            [10.0, 10.3, 100.0].into(),
-            palette::Srgba::new(200.5, 200.05, 0.65, 1.0),
-        );
-        */
+        // Lines follow the nodes every frame
+        let color = |c: [f32; 4]| palette::Srgba::new(c[0], c[1], c[2], c[3]);
+        let point = |p: &Vector3<f32>| Point3::new(p.x, p.y, p.z);
+        for (edges, c) in [(&graph.edges, theme.edge), (&graph.links, theme.link)].iter() {
+            for (a, b) in edges.iter() {
+                if let (Some(pa), Some(pb)) = (transforms.get(*a), transforms.get(*b)) {
+                    dl.draw_line(point(pa.translation()), point(pb.translation()), color(*c));
+                }
+            }
+        }
+    }
+}
 
 
-        /*
-        let light_angular_velocity = -1.0;
-        let light_orbit_radius = 15.0;
-        let light_z = 6.0;
-        let camera_angular_velocity = 1.0;
+// Radians per pixel of mouse drag, and per second of arrow keys
+const ORBIT_SPEED: f32 = 0.01;
+const ORBIT_KEY_SPEED: f32 = 1.5;
+// Zoom factor per wheel step
+const ORBIT_ZOOM: f32 = 0.1;
+// How fast the camera catches up with the goals, higher is snappier
+const ORBIT_DAMPING: f32 = 10.0;
 
-        state.light_angle += light_angular_velocity * time.delta_seconds();
-        state.camera_angle += camera_angular_velocity * time.delta_seconds();
+// Orbit camera, drag with "orbit" held to rotate around the target, with "pan" held to move
+// the target, "zoom" to get closer. The arrow key axes rotate as well.
+#[derive(Default, SystemDesc)]
+pub struct OrbitCameraSystem {
+    last_mouse: Option<(f32, f32)>,
+}
 
-        let delta_rot: UnitQuaternion<f32> = UnitQuaternion::from_axis_angle(
-            &Vector3::z_axis(),
-            camera_angular_velocity * time.delta_seconds(),
-        );
+impl<'a> System<'a> for OrbitCameraSystem {
+    type SystemData = (
+        WriteStorage<'a, OrbitCamera>,
+        WriteStorage<'a, Transform>,
+        Read<'a, core::timing::Time>,
+        Read<'a, input::InputHandler<input::StringBindings>>,
+        ReadExpect<'a, window::ScreenDimensions>,
+    );
+    fn run(&mut self, (mut orbits, mut transforms, time, input, screen): Self::SystemData) {
+        let mouse = input.mouse_position();
+        let (dx, dy) = match (mouse, self.last_mouse) {
+            (Some((x, y)), Some((last_x, last_y))) => (x - last_x, y - last_y),
+            _ => (0.0, 0.0),
+        };
+        self.last_mouse = mouse;
 
-        for (_, transform) in (&camera, &mut transforms).join() {
-            *transform.isometry_mut() = delta_rot * transform.isometry();
-        }
+        let dt = time.delta_seconds();
+        let orbiting = input.action_is_down("orbit").unwrap_or(false);
+        let panning = input.action_is_down("pan").unwrap_or(false);
+        let zoom = input.axis_value("zoom").unwrap_or(0.0);
+        let keys = (input.axis_value("check_right").unwrap_or(0.0), input.axis_value("check_up").unwrap_or(0.0));
 
-        for (point_light, transform) in (&mut lights, &mut transforms).join().filter_map(|(light, transform)| {
-            if let renderer::light::Light::Point(ref mut point_light) = *light {
-                Some((point_light, transform))
+        for (orbit, transform) in (&mut orbits, &mut transforms).join() {
+            if !orbit.flat {
+                if orbiting {
+                    orbit.goal_yaw -= dx * ORBIT_SPEED;
+                    orbit.goal_pitch += dy * ORBIT_SPEED;
+                }
+                orbit.goal_yaw += keys.0 * ORBIT_KEY_SPEED * dt;
+                orbit.goal_pitch += keys.1 * ORBIT_KEY_SPEED * dt;
+                // Never flip over the poles
+                orbit.goal_pitch = orbit.goal_pitch.max(-1.5).min(1.5);
+            }
+            if panning {
+                // Move the target with the screen, faster when further away
+                let rotation = transform.rotation();
+                let right = rotation * Vector3::x();
+                let up = rotation * Vector3::y();
+                let scale = if orbit.flat {
+                    transform.scale().x * orbit.half_height * 2.0 / screen.height()
+                } else {
+                    orbit.distance * 0.0015
+                };
+                orbit.goal_target += (-right * dx + up * dy) * scale;
+            }
+            if zoom != 0.0 {
+                orbit.goal_distance *= (1.0 - zoom * ORBIT_ZOOM).max(0.1);
+                orbit.goal_distance = orbit.goal_distance.max(1.0);
+            }
+
+            let ease = 1.0 - (-ORBIT_DAMPING * dt).exp();
+            orbit.target += (orbit.goal_target - orbit.target) * ease;
+            orbit.yaw += (orbit.goal_yaw - orbit.yaw) * ease;
+            orbit.pitch += (orbit.goal_pitch - orbit.pitch) * ease;
+            orbit.distance += (orbit.goal_distance - orbit.distance) * ease;
+
+            if orbit.flat {
+                // The orthographic view keeps its distance, scaling it shows more or less of the plane
+                let zoom = orbit.distance / ORBIT_FLAT_DISTANCE;
+                transform.set_scale(Vector3::new(zoom, zoom, 1.0));
+                transform.set_translation(orbit.target + Vector3::new(0.0, 0.0, ORBIT_FLAT_DISTANCE));
             } else {
+                transform.set_translation(orbit.eye());
+                transform.face_towards(orbit.target, Vector3::y());
+            }
+        }
+    }
+}
+
+
+// Mouse travel in pixels still counting as a click rather than a drag
+const PICK_CLICK: f32 = 4.0;
+// Node spheres grow by this much while selected
+const SELECTED_SCALE: f32 = 1.5;
+
+// Selects the node under the cursor on "select" clicks, by casting a ray from the camera
+// through the cursor against the node spheres. Clicking the background clears the selection.
+#[derive(Default, SystemDesc)]
+pub struct PickSystem {
+    pressed: Option<(f32, f32)>,
+}
+
+impl<'a> System<'a> for PickSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, GodsNode>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, renderer::Camera>,
+        ReadExpect<'a, renderer::ActiveCamera>,
+        ReadExpect<'a, window::ScreenDimensions>,
+        Read<'a, input::InputHandler<input::StringBindings>>,
+        Write<'a, Selection>,
+    );
+    fn run(&mut self, (entities, nodes, transforms, cameras, active_camera, screen, input, mut selection): Self::SystemData) {
+        let down = input.action_is_down("select").unwrap_or(false);
+        let mouse = input.mouse_position();
+        let click = match (down, self.pressed, mouse) {
+            (true, None, _) => {
+                self.pressed = mouse;
                 None
+            },
+            (false, Some((x, y)), Some((mx, my))) => {
+                self.pressed = None;
+                if (mx - x).hypot(my - y) <= PICK_CLICK { Some((mx, my)) } else { None }
+            },
+            (false, Some(_), None) => {
+                self.pressed = None;
+                None
+            },
+            _ => None,
+        };
+        let (x, y) = match click {
+            Some(click) => click,
+            None => return,
+        };
+
+        let camera_entity = match active_camera.entity {
+            Some(entity) => entity,
+            None => return,
+        };
+        let (camera, camera_transform) = match (cameras.get(camera_entity), transforms.get(camera_entity)) {
+            (Some(camera), Some(transform)) => (camera, transform),
+            _ => return,
+        };
+        let ray = camera.projection().screen_ray(
+            Point2::new(x, y),
+            Vector2::new(screen.width(), screen.height()),
+            camera_transform,
+        );
+        let direction = ray.direction.normalize();
+
+        // Nearest sphere hit along the ray
+        let mut nearest: Option<(f32, Entity)> = None;
+        for (entity, _, transform) in (&entities, &nodes, &transforms).join() {
+            let radius = NODE_RADIUS * transform.scale().x;
+            let to_center = transform.translation() - ray.origin.coords;
+            let along = to_center.dot(&direction);
+            let miss = to_center.norm_squared() - along * along;
+            if along < 0.0 || miss > radius * radius {
+                continue;
+            }
+            let hit = along - (radius * radius - miss).sqrt();
+            if nearest.map(|(distance, _)| hit < distance).unwrap_or(true) {
+                nearest = Some((hit, entity));
             }
-        }) {
-            transform.set_translation_xyz(
-                light_orbit_radius * state.light_angle.cos(),
-                light_orbit_radius * state.light_angle.sin(),
-                light_z,
-            );
-            point_light.color = state.light_color;
         }
 
-        if self.fps_display.is_none() {
-            self.fps_display = finder.find("fps_text");
+        match nearest {
+            Some((_, entity)) if selection.entity == Some(entity) => selection.toggle = true,
+            Some((_, entity)) => {
+                let path = nodes.get(entity).map(|node| node.node.read().unwrap().read_path());
+                selection.select(path, Some(entity));
+            },
+            None => selection.select(None, None),
         }
-        if let Some(fps_entity) = self.fps_display {
-            if let Some(fps_display) = ui_text.get_mut(fps_entity) {
-                if time.frame_number() % 20 == 0 {
-                    fps_display.text = format!("FPS: {:.*}", 2, fps_counter.sampled_fps());
+    }
+}
+
+// Shows the selection, grows the selected node, fills the panel and prints the node
+#[derive(Default, SystemDesc)]
+pub struct SelectionSystem {
+    highlighted: Option<Entity>,
+}
+
+impl<'a> System<'a> for SelectionSystem {
+    type SystemData = (
+        ReadStorage<'a, GodsNode>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, ui::UiText>,
+        Write<'a, Selection>,
+        Write<'a, DebugLines>,
+        Read<'a, Theme>,
+    );
+    fn run(&mut self, (nodes, mut transforms, mut texts, mut selection, mut dl, theme): Self::SystemData) {
+        if selection.changed {
+            selection.changed = false;
+            if let Some(transform) = self.highlighted.take().and_then(|entity| transforms.get_mut(entity)) {
+                transform.set_scale(Vector3::new(1.0, 1.0, 1.0));
+            }
+
+            let text = match selection.entity.and_then(|entity| nodes.get(entity)) {
+                Some(node) => node.node.read().unwrap().describe(),
+                None => String::new(),
+            };
+            if !text.is_empty() {
+                println!("Selected {}", text.replace("\n", "\n  "));
+            }
+            if let Some(panel) = selection.panel.and_then(|panel| texts.get_mut(panel)) {
+                panel.text = text;
+            }
+
+            if let Some(entity) = selection.entity {
+                if let Some(transform) = transforms.get_mut(entity) {
+                    transform.set_scale(Vector3::new(SELECTED_SCALE, SELECTED_SCALE, SELECTED_SCALE));
+                    self.highlighted = Some(entity);
                 }
             }
         }
-        */
+
+        // Wire sphere around the selected node, wherever it moves
+        if let Some(transform) = selection.entity.and_then(|entity| transforms.get(entity)) {
+            let p = transform.translation();
+            let c = theme.link;
+            dl.draw_sphere(Point3::new(p.x, p.y, p.z), NODE_RADIUS * SELECTED_SCALE * 1.2, 8, 8, palette::Srgba::new(c[0], c[1], c[2], c[3]));
+        }
     }
 }
 
 
+// Rings the nodes matching the search, wherever they move
+#[derive(Default, SystemDesc)]
+pub struct SearchSystem;
 
+impl<'a> System<'a> for SearchSystem {
+    type SystemData = (
+        ReadStorage<'a, Transform>,
+        Read<'a, Search>,
+        Write<'a, DebugLines>,
+        Read<'a, Theme>,
+    );
+    fn run(&mut self, (transforms, search, mut dl, theme): Self::SystemData) {
+        let c = theme.highlight;
+        for transform in search.entities.iter().filter_map(|entity| transforms.get(*entity)) {
+            let p = transform.translation();
+            dl.draw_sphere(Point3::new(p.x, p.y, p.z), NODE_RADIUS * 1.4, 6, 6, palette::Srgba::new(c[0], c[1], c[2], c[3]));
+        }
+    }
+}
+
+
+// Distance at which labels have their nominal size
+const LABEL_DISTANCE: f32 = 50.0;
+// Labels fade out towards this distance
+const LABEL_FADE: f32 = 400.0;
+// Labels are drawn above their node, in pixels
+const LABEL_LIFT: f32 = 18.0;
+
+// Moves every label to the screen position of its node, hides the ones behind the camera,
+// scales and fades them with distance, and hides labels overlapping nearer ones
+#[derive(Default, SystemDesc)]
+pub struct LabelSystem;
 
+impl<'a> System<'a> for LabelSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, NodeLabel>,
+        WriteStorage<'a, ui::UiTransform>,
+        WriteStorage<'a, ui::UiText>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, renderer::Camera>,
+        ReadStorage<'a, OrbitCamera>,
+        ReadExpect<'a, renderer::ActiveCamera>,
+        ReadExpect<'a, window::ScreenDimensions>,
+        Read<'a, Selection>,
+    );
+    fn run(&mut self, (entities, labels, mut ui_transforms, mut texts, transforms, cameras, orbits, active_camera, screen, selection): Self::SystemData) {
+        let camera_entity = match active_camera.entity {
+            Some(entity) => entity,
+            None => return,
+        };
+        let (camera, camera_transform) = match (cameras.get(camera_entity), transforms.get(camera_entity)) {
+            (Some(camera), Some(transform)) => (camera, transform),
+            _ => return,
+        };
+        let flat = orbits.get(camera_entity).map(|orbit| orbit.flat).unwrap_or(false);
+        let eye = *camera_transform.translation();
+        let forward = camera_transform.rotation() * -Vector3::z();
+        let diagonal = Vector2::new(screen.width(), screen.height());
+
+        // Visible labels with their screen position, size and distance
+        let mut shown: Vec<(Entity, f32, f32, f32, f32, bool)> = Vec::new();
+        for (entity, label) in (&entities, &labels).join() {
+            let position = match transforms.get(label.node) {
+                Some(transform) => *transform.translation(),
+                None => continue,
+            };
+            let depth = (position - eye).dot(&forward);
+            if depth <= 0.0 {
+                if let Some(text) = texts.get_mut(entity) {
+                    text.color[3] = 0.0;
+                }
+                continue;
+            }
+
+            let screen_position = camera.projection().world_to_screen(Point3::new(position.x, position.y, position.z), diagonal, camera_transform);
+            // Orthographic views keep sizes, they scale with the zoom instead
+            let scale = if flat {
+                1.0 / camera_transform.scale().x.max(0.01)
+            } else {
+                LABEL_DISTANCE / depth
+            };
+            let size = (label.size * scale).max(label.size * 0.5).min(label.size * 1.5);
+            let selected = selection.entity == Some(label.node);
+            shown.push((entity, screen_position.x, screen_position.y, size, if flat { 0.0 } else { depth }, label.title || selected));
+        }
+
+        // Titles and the selection first, then the nearest labels
+        shown.sort_by(|a, b| b.5.cmp(&a.5).then(a.4.partial_cmp(&b.4).unwrap_or(std::cmp::Ordering::Equal)));
+        let mut taken: Vec<(f32, f32, f32, f32)> = Vec::new();
+        for (entity, x, y, size, depth, _) in shown.into_iter() {
+            let text = match texts.get_mut(entity) {
+                Some(text) => text,
+                None => continue,
+            };
+            let width = text.text.chars().count() as f32 * size * 0.55;
+            let rect = (x - width / 2.0, y - LABEL_LIFT - size, width, size);
+            let overlaps = taken.iter().any(|&(tx, ty, tw, th)| {
+                rect.0 < tx + tw && tx < rect.0 + rect.2 && rect.1 < ty + th && ty < rect.1 + rect.3
+            });
+            if overlaps {
+                text.color[3] = 0.0;
+                continue;
+            }
+            taken.push(rect);
+
+            text.font_size = size;
+            text.color[3] = (1.0 - depth / LABEL_FADE).max(0.2).min(1.0);
+            if let Some(ui_transform) = ui_transforms.get_mut(entity) {
+                // Ui coordinates grow upwards from the bottom left corner
+                ui_transform.local_x = x;
+                ui_transform.local_y = screen.height() - y + LABEL_LIFT;
+                ui_transform.width = width.max(1.0);
+                ui_transform.height = size * 1.2;
+            }
+        }
+    }
+}
@@ -1,58 +1,105 @@
 use std::sync::{Arc, Weak, RwLock};
-use std::collections::{ HashMap, VecDeque };
+use std::collections::{ HashMap, HashSet, VecDeque };
+use std::cmp::Ordering;
 use crate::node::*;
 use std::marker;
 use std::f64::consts::PI;
 use serde_json::Value;
 use crate::misc::*;
+use crate::config::*;
+use crate::layout::Layout;
+use log::{debug, info};
 
 pub struct Godswoods<N, T> where N: GodsnodeProto, T: GodswoodProto<N> {
     pub woods: Arc<RwLock<HashMap<String, Arc<RwLock<Godswood<N, T>>>>>>,
+    // Wood names in the order they were added
+    pub names: Vec<String>,
     pub store: Arc<Godsstore<N>>,
+    pub arrangement: Arrangement,
 }
 
 impl<N: GodsnodeProto, T: GodswoodProto<N>> Godswoods<N, T> {
     pub fn new() -> Self {
         Self {
             woods: Arc::new(RwLock::new(HashMap::new())),
+            names: Vec::new(),
             store: GodsstoreProto::<N>::new(),
+            arrangement: Arrangement::new(),
         }
     }
 
-    pub fn add_wood(&mut self, raw: &Value) {
+    // The root is named `default_name` when neither the settings nor the document name it
+    pub fn add_wood(&mut self, raw: &Value, default_name: &str) -> Result<(), ParseError> {
+        let (mut config, tree) = WoodConfig::parse(raw)?;
+        if config.name.is_none() && config.keys.read_name(tree).is_none() {
+            config.name = Some(default_name.to_string());
+        }
         let mut wood: T = T::default(self.store.clone());
-        wood.parse_from_json(raw);
+        wood.set_config(config.clone());
+        wood.parse_from_json(tree)?;
+        self.plant(wood, &config)
+    }
+
+    // Add a wood built node by node in the store of the forest instead of parsed
+    pub fn add_tree(&mut self, root: &Arc<RwLock<N>>) -> Result<(), ParseError> {
+        let mut wood: T = T::default(self.store.clone());
+        wood.set_root(Arc::downgrade(root));
+        self.plant(wood, &WoodConfig::new())
+    }
+
+    // Index the nodes of a wood under their paths and add the wood, links are resolved
+    // once all woods are indexed
+    fn plant(&mut self, mut wood: T, config: &WoodConfig) -> Result<(), ParseError> {
+        let name = wood.read_name();
+        if self.names.contains(&name) {
+            // Paths of both woods would collide in the shared store
+            return Err(ParseError::new(format!("Duplicate wood name {}", name)));
+        }
         wood.init_nodes();
         let mut woods = self.woods.write().unwrap();
-        let name = wood.read_name();
-        let mut godswood = Godswood {
-            wood,
-            scales: HashMap::new(),
-            base_scale: 1.0,
-            base_gap: 10.0,
-            ph: marker::PhantomData,
-        };
+        let title = config.title.clone().unwrap_or_else(|| name.clone());
+        let godswood = Godswood::new(wood, title, config.offset);
+
+        woods.insert(name.clone(), Arc::new(RwLock::new(godswood)));
+        self.names.push(name);
+        Ok(())
+    }
 
-        godswood.calculate_scales();
-        woods.insert(name, Arc::new(RwLock::new(godswood)));
+    // Resolve the links of all parsed woods, absolute links can point into any wood
+    pub fn link_woods(&self) -> Result<(), ParseError> {
+        for wood in self.ordered().iter() {
+            wood.write().unwrap().wood.link_nodes()?;
+        }
+        Ok(())
+    }
+
+    // Woods in the order they were added
+    pub fn ordered(&self) -> Vec<Arc<RwLock<Godswood<N, T>>>> {
+        let woods = self.woods.read().unwrap();
+        self.names.iter().filter_map(|name| woods.get(name).cloned()).collect()
     }
 }
 
 pub trait GodswoodProto<N> where N: GodsnodeProto {
     fn default(store: Arc<Godsstore<N>>) -> Self;
+    fn set_config(&mut self, config: WoodConfig);
     fn init_nodes(&mut self);
+    fn link_nodes(&mut self) -> Result<(), ParseError>;
     fn get_nodes_by_depths(&self) -> &Godsnodes<N>;
     fn get_depth(&self) -> usize;
     fn get_root(&self) -> Weak<RwLock<N>>;
-    fn parse_from_json(&mut self, raw: &Value);
+    fn set_root(&mut self, root: Weak<RwLock<N>>);
+    fn parse_from_json(&mut self, raw: &Value) -> Result<(), ParseError>;
     fn read_name(&self) -> String;
 }
 
 pub struct Godswood<N, T> where N: GodsnodeProto, T: GodswoodProto<N> {
     pub wood: T,
-    pub scales: HashMap<usize, f32>,
-    pub base_scale: f32,
     pub base_gap: f32,
+    // Position of the root in the scene
+    pub origin: (f32, f32, f32),
+    pub offset: (f32, f32, f32),
+    pub title: String,
     ph: marker::PhantomData<N>,
 }
 
@@ -60,56 +107,48 @@ pub struct Godspoint {
 
 }
 
+#[derive(Default, Debug)]
+pub struct GodswoodStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    pub max_children: usize,
+    pub links: usize,
+}
+
 impl<N: GodsnodeProto, T: GodswoodProto<N>> Godswood<N, T> {
-    pub fn new(wood: T) -> Godswood<N, T> {
+    // Wood placed at offset until the forest is arranged
+    pub fn new(wood: T, title: String, offset: (f32, f32, f32)) -> Godswood<N, T> {
         Godswood {
             wood,
-            scales: HashMap::new(),
-            base_scale: 1.0,
-            base_gap: 5.0,
+            base_gap: 10.0,
+            origin: offset,
+            offset,
+            title,
             ph: marker::PhantomData,
         }
     }
 
-    fn calculate_scales(&mut self) {
-        println!("Initializing scales for wood");
+    pub fn stats(&self) -> GodswoodStats {
+        let mut stats = GodswoodStats::default();
+        stats.depth = self.wood.get_depth();
         let nodes = self.wood.get_nodes_by_depths();
-        let depth = self.wood.get_depth();
         let nodes = nodes.read().unwrap();
-        for i in 1..depth {
-            let items = nodes.get(&i).unwrap();
-            let mut kids_max = 0;
+        for items in nodes.values() {
             for item in items.iter() {
                 if let Some(node) = item.upgrade() {
                     let node = node.read().unwrap();
-                    let kids = node.get_children();
-                    if kids.len() > kids_max {
-                        kids_max = kids.len();
+                    let kids = node.get_children().len();
+                    stats.nodes += 1;
+                    stats.links += node.get_links().len();
+                    stats.max_children = stats.max_children.max(kids);
+                    if kids == 0 {
+                        stats.leaves += 1;
                     }
-                } else {
-                    // error!("Unexpected weak node lost connection");
                 }
             }
-
-            let scale: f32;
-
-            if kids_max <= 1 {
-                scale = 1.0;
-            } else {
-                let angle = PI as f32/ kids_max as f32;
-                scale = 1.0 / angle.sin() + 1.0;
-            }
-
-            println!("Calculated scale {} for depth {}", scale, i);
-            self.scales.insert(i, scale);
-        }
-        self.scales.insert(depth, 1.0);
-        let mut scale = 1.0f32;
-        for i in (1..depth).rev() {
-            let v = self.scales.get_mut(&i).unwrap();
-            *v *= scale;
-            scale = *v;
         }
+        stats
     }
 
     pub fn render_test(&self) {
@@ -143,17 +182,161 @@ impl<N: GodsnodeProto, T: GodswoodProto<N>> Godswood<N, T> {
 }
 
 // ---------------- Sample tree -------------------------------
+pub type Forest = Godswoods<NodeProto, TreeProto>;
+pub type Wood = Godswood<NodeProto, TreeProto>;
+
+impl Forest {
+    pub fn load(forest: &ForestConfig) -> Result<Self, ParseError> {
+        let mut woods = Self::new();
+        for (i, raw) in forest.woods.iter().enumerate() {
+            info!("Adding new wood");
+            // Unnamed woods are told apart by their file, or by their place in the forest
+            let name = forest.stems.get(i).and_then(|stem| stem.clone()).unwrap_or_else(|| format!("wood{}", i + 1));
+            woods.add_wood(raw, &name)?;
+        }
+        woods.link_woods()?;
+        woods.arrangement = forest.arrangement.clone();
+        woods.arrange(&forest.layout);
+        Ok(woods)
+    }
+
+    // Place the woods next to each other, the origin of each wood is where its root goes,
+    // on the ground for the cone layout and side by side in the x-y plane for flat ones
+    pub fn arrange(&self, layout: &LayoutConfig) {
+        let arrangement = &self.arrangement;
+        let engine = layout.engine();
+        let woods = self.ordered();
+        let count = woods.len();
+        if count == 0 {
+            return;
+        }
+
+        let spacing = arrangement.spacing.unwrap_or_else(|| {
+            woods.iter().map(|wood| {
+                let wood = wood.read().unwrap();
+                engine.extent(&wood) + wood.base_gap
+            }).fold(0f32, f32::max)
+        });
+
+        let columns = arrangement.columns.unwrap_or((count as f32).sqrt().ceil() as usize).min(count);
+        let rows = (count + columns - 1) / columns;
+        for (i, wood) in woods.iter().enumerate() {
+            let (x, z) = match arrangement.kind {
+                ArrangementKind::Grid => {
+                    let column = (i % columns) as f32 - (columns - 1) as f32 / 2.0;
+                    let row = (i / columns) as f32 - (rows - 1) as f32 / 2.0;
+                    (column * spacing, row * spacing)
+                },
+                ArrangementKind::Circle => {
+                    if count == 1 {
+                        (0.0, 0.0)
+                    } else {
+                        // Neighbours on the circle are spacing apart
+                        let angle = PI as f32 / count as f32;
+                        let radius = spacing / (2.0 * angle.sin());
+                        let angle = angle * 2.0 * i as f32;
+                        (radius * angle.cos(), radius * angle.sin())
+                    }
+                }
+            };
+            let mut wood = wood.write().unwrap();
+            let (dx, dy, dz) = wood.offset;
+            wood.origin = if layout.kind.is_flat() {
+                (x + dx, dy - z, dz)
+            } else {
+                (x + dx, dy, z + dz)
+            };
+        }
+    }
+
+    // Paths of the nodes to collapse so that the first depth levels of every wood show
+    pub fn expand_to(&self, depth: usize) -> HashSet<String> {
+        let mut paths = HashSet::new();
+        for wood in self.ordered().iter() {
+            let wood = wood.read().unwrap();
+            let nodes = wood.wood.get_nodes_by_depths();
+            let nodes = nodes.read().unwrap();
+            for node in nodes.get(&depth).iter().flat_map(|items| items.iter()).filter_map(|item| item.upgrade()) {
+                let node = node.read().unwrap();
+                if !node.children.is_empty() {
+                    paths.insert(node.read_path());
+                }
+            }
+        }
+        paths
+    }
+
+    // Forest of a single wood rooted at the node with the given path, sharing the nodes
+    // and the store of this one
+    pub fn subtree(&self, path: &str) -> Option<Forest> {
+        let node = self.store.get_weak_node(&path.to_string())?.upgrade()?;
+        let wood = self.ordered().into_iter().find(|wood| {
+            let root = wood.read().unwrap().wood.get_root().upgrade();
+            let root = root.map(|root| root.read().unwrap().read_path()).unwrap_or_default();
+            path == root || path.starts_with(&format!("{}.", root))
+        })?;
+        let wood = wood.read().unwrap();
+        let name = node.read().unwrap().name.clone();
+
+        let mut woods = Forest::new();
+        woods.store = self.store.clone();
+        woods.arrangement = self.arrangement.clone();
+        let godswood = Godswood::new(wood.wood.subtree(&node), name.clone(), (0.0, 0.0, 0.0));
+        woods.woods.write().unwrap().insert(name.clone(), Arc::new(RwLock::new(godswood)));
+        woods.names.push(name);
+        Some(woods)
+    }
+
+    // Collapse the nodes with the given paths and expand all others
+    pub fn collapse(&self, paths: &HashSet<String>) {
+        for wood in self.ordered().iter() {
+            let wood = wood.read().unwrap();
+            let nodes = wood.wood.get_nodes_by_depths();
+            let nodes = nodes.read().unwrap();
+            for node in nodes.values().flat_map(|items| items.iter()).filter_map(|item| item.upgrade()) {
+                let mut node = node.write().unwrap();
+                node.collapsed = !node.children.is_empty() && paths.contains(&node.read_path());
+            }
+        }
+    }
+}
+
 pub struct TreeProto {
     depth: usize,
     nodes_by_depth: Arc<RwLock<HashMap<usize, Vec<Weak<Node>>>>>,
     root: Weak<Node>,
     store: Arc<Store>,
+    config: WoodConfig,
 }
 
 impl TreeProto {
+    // Tree of the nodes below root, the nodes keep their paths in the whole wood
+    fn subtree(&self, root: &Arc<Node>) -> TreeProto {
+        let mut nodes_by_depth: HashMap<usize, Vec<Weak<Node>>> = HashMap::new();
+        let mut depth = 0;
+        let mut tasks = VecDeque::new();
+        tasks.push_back((1, Arc::downgrade(root)));
+        while let Some((d, item)) = tasks.pop_front() {
+            if let Some(node) = item.upgrade() {
+                depth = depth.max(d);
+                for child in node.read().unwrap().children.iter() {
+                    tasks.push_back((d + 1, child.clone()));
+                }
+                nodes_by_depth.entry(d).or_insert_with(Vec::new).push(item);
+            }
+        }
+        TreeProto {
+            depth,
+            nodes_by_depth: Arc::new(RwLock::new(nodes_by_depth)),
+            root: Arc::downgrade(root),
+            store: self.store.clone(),
+            config: self.config.clone(),
+        }
+    }
+
     fn init_nodes(&mut self) {
         let nodes_by_depth = self.nodes_by_depth.clone();
-        println!("Initializing nodes for wood");
+        debug!("Initializing nodes for wood");
         if let Some(node) = self.root.upgrade() {
             let mut nodes_by_depth = nodes_by_depth.write().unwrap();
 
@@ -194,7 +377,7 @@ impl TreeProto {
                         let mut kid_app_meta = task.app_meta.clone();
                         let mut kid = node.write().unwrap();
                         kid_app_meta.path.append(&kid.name);
-                        println!("Initializing {}", kid_app_meta.path.read());
+                        debug!("Initializing {}", kid_app_meta.path.read());
                         self.depth = kid_app_meta.path.read_depth();
                         kid.app_meta_map.insert(app_name.clone(), kid_app_meta.clone());
                         self.store.update_index(&kid_app_meta.path.read(), kid.id);
@@ -214,6 +397,88 @@ impl TreeProto {
 
     }
 
+    // Resolve the links of every node into extra parent/child edges, links are node paths,
+    // either absolute (.app.node1.node3) or relative to the wood root (node1.node3)
+    fn link_nodes(&mut self) -> Result<(), ParseError> {
+        if self.config.mode != ParseMode::Children {
+            return Ok(());
+        }
+        let key = self.config.keys.links.clone();
+        let root_path = match self.root.upgrade() {
+            Some(root) => format!(".{}", GodsnodePath::escape(&root.read().unwrap().name)),
+            None => return Ok(()),
+        };
+
+        let nodes_by_depth = self.nodes_by_depth.read().unwrap();
+        for depth in 1..self.depth + 1 {
+            let items = match nodes_by_depth.get(&depth) {
+                Some(items) => items,
+                None => continue,
+            };
+            for item in items.iter() {
+                let node = match item.upgrade() {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let (name, refs) = {
+                    let state = node.read().unwrap();
+                    let refs: Vec<String> = match state.get_attribute(&key).and_then(|v| v.as_array()) {
+                        Some(refs) => refs.iter().filter_map(|v| v.as_str()).map(|v| v.to_string()).collect(),
+                        None => continue,
+                    };
+                    (state.name.clone(), refs)
+                };
+
+                for path in refs.iter() {
+                    let path = if path.starts_with('.') { path.clone() } else { format!("{}.{}", root_path, path) };
+                    let target = match self.store.get_weak_node(&path).and_then(|n| n.upgrade()) {
+                        Some(target) => target,
+                        None => return Err(ParseError::new(format!("Node {} links to unknown node {}", name, path))),
+                    };
+                    if TreeProto::reaches(&target, &node) {
+                        return Err(ParseError::new(format!("Link from node {} to {} would create a cycle", name, path)));
+                    }
+
+                    let target_id = target.read().unwrap().id;
+                    let linked = {
+                        let state = node.read().unwrap();
+                        state.children.iter().chain(state.links.iter())
+                            .filter_map(|n| n.upgrade())
+                            .any(|n| n.read().unwrap().id == target_id)
+                    };
+                    if linked {
+                        continue;
+                    }
+                    node.write().unwrap().add_link(Arc::downgrade(&target));
+                    target.write().unwrap().add_parent(Arc::downgrade(&node));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Whether `to` can be reached from `from` following children and links
+    fn reaches(from: &Arc<Node>, to: &Arc<Node>) -> bool {
+        let to = to.read().unwrap().id;
+        let mut visited = HashSet::new();
+        let mut tasks = vec![from.clone()];
+        while let Some(node) = tasks.pop() {
+            let node = node.read().unwrap();
+            if node.id == to {
+                return true;
+            }
+            if !visited.insert(node.id) {
+                continue;
+            }
+            for kid in node.children.iter().chain(node.links.iter()) {
+                if let Some(kid) = kid.upgrade() {
+                    tasks.push(kid);
+                }
+            }
+        }
+        false
+    }
+
     // Sample application tree
     // app:
     //   children:
@@ -223,31 +488,171 @@ impl TreeProto {
     //             children:
     //     node2:
     //       children
+    //
+    // children can also be an array of nodes carrying their own name:
+    // app:
+    //   children:
+    //     - name: node1
+    //       children:
+    //         - name: node3
+    //     - name: node2
 
-    pub fn parse(&mut self, raw:& Value) {
+    pub fn parse(&mut self, raw:& Value) -> Result<(), ParseError> {
+        let keys = self.config.keys.clone();
         let root = self.store.add_app_node(&raw);
         self.root = Arc::downgrade(&root);
-        if let Some(children) = raw["children"].as_object() {
-            if !children.is_empty() {
-                TreeProto::parse_children(&root, &children, &mut self.store);
+        {
+            let mut state = root.write().unwrap();
+            if let Some(name) = &self.config.name {
+                state.name = name.clone();
+            } else if let Some(name) = keys.read_name(raw) {
+                state.name = name;
             }
+            state.display_name = raw.get_string(keys.display_name.as_str(), state.display_name.clone());
+            state.attributes.remove(keys.children.as_str());
         }
+        match self.config.mode {
+            ParseMode::Children => {
+                TreeProto::parse_children(&root, &raw[keys.children.as_str()], &self.config, &mut self.store)?;
+            },
+            ParseMode::Generic => {
+                TreeProto::keep_scalar_attributes(&root);
+                TreeProto::parse_value(&root, raw, &self.config, &mut self.store);
+            }
+        }
+        Ok(())
     }
 
-    pub fn parse_children(parent_node: &Arc<Node>, children: & JsonMap, store: &mut Arc<Store>) {
-        for (name, raw) in children.iter() {
-            let mut node = store.add_node(&raw, name.clone());
-            if let Some(sub_children) = raw["children"].as_object() {
-                if ! sub_children.is_empty() {
-                    TreeProto::parse_children(&mut node, sub_children, store);
+    // Returns the number of descendants parsed under parent_node
+    pub fn parse_children(parent_node: &Arc<Node>, children: &Value, config: &WoodConfig, store: &mut Arc<Store>) -> Result<usize, ParseError> {
+        let keys = &config.keys;
+        let items: Vec<(String, &Value)> = match children {
+            Value::Null => return Ok(0),
+            Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            Value::Array(list) => {
+                let mut items = Vec::new();
+                for (i, raw) in list.iter().enumerate() {
+                    match keys.read_name(raw) {
+                        Some(name) => items.push((name, raw)),
+                        None => {
+                            return Err(ParseError::new(format!(
+                                "Child #{} of node {} has no usable \"{}\" field",
+                                i, parent_node.read().unwrap().name, keys.name)));
+                        }
+                    }
                 }
+                items
+            },
+            _ => {
+                return Err(ParseError::new(format!(
+                    "Field \"{}\" of node {} must be an object or an array",
+                    keys.children, parent_node.read().unwrap().name)));
             }
+        };
+
+        let mut kids = Vec::new();
+        for (name, raw) in items.into_iter() {
+            let node = store.add_node(&raw, name);
+            {
+                let mut state = node.write().unwrap();
+                state.display_name = raw.get_string(keys.display_name.as_str(), state.display_name.clone());
+                state.attributes.remove(keys.children.as_str());
+            }
+            let size = TreeProto::parse_children(&node, &raw[keys.children.as_str()], config, store)?;
+            kids.push(ParsedNode::new(node, size, raw, &config.sort));
+        }
+        Ok(TreeProto::link_children(parent_node, kids, &config.sort))
+    }
+
+    // Generic json mode
+    // Object keys and array indices become node names, scalars become leaves
+    pub fn parse_value(parent_node: &Arc<Node>, raw: &Value, config: &WoodConfig, store: &mut Arc<Store>) -> usize {
+        let items: Vec<(String, &Value)> = match raw {
+            Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            Value::Array(list) => list.iter().enumerate().map(|(i, v)| (i.to_string(), v)).collect(),
+            _ => return 0,
+        };
+
+        let mut kids = Vec::new();
+        for (name, raw) in items.into_iter() {
+            let (node, size) = match raw {
+                Value::Object(_) | Value::Array(_) => {
+                    let node = store.add_node(raw, name);
+                    TreeProto::keep_scalar_attributes(&node);
+                    let size = TreeProto::parse_value(&node, raw, config, store);
+                    (node, size)
+                },
+                _ => (store.add_leaf_node(&name, raw), 0),
+            };
+            kids.push(ParsedNode::new(node, size, raw, &config.sort));
+        }
+        TreeProto::link_children(parent_node, kids, &config.sort)
+    }
+
+    // Nested values are children already in generic mode, keep the scalar fields only
+    fn keep_scalar_attributes(node: &Arc<Node>) {
+        let mut state = node.write().unwrap();
+        state.attributes = state.attributes.iter()
+            .filter(|(_, value)| GodsnodeValueType::of(value).is_scalar())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+    }
+
+    // Link parsed children to their parent in the configured sibling order,
+    // returns the number of descendants of the parent
+    fn link_children(parent_node: &Arc<Node>, mut kids: Vec<ParsedNode>, sort: &SortConfig) -> usize {
+        ParsedNode::sort(&mut kids, sort);
+        let mut size = 0;
+        for kid in kids.iter() {
+            size += kid.size + 1;
             let mut parent = parent_node.write().unwrap();
-            parent.add_child(Arc::downgrade(&node));
-            let mut child = node.write().unwrap();
+            parent.add_child(Arc::downgrade(&kid.node));
+            let mut child = kid.node.write().unwrap();
             child.add_parent(Arc::downgrade(parent_node));
             // info!("linking parent {} with child {}", parent.name, child.name);
         }
+        size
+    }
+}
+
+// Parsed child waiting to be linked to its parent
+struct ParsedNode {
+    node: Arc<Node>,
+    name: String,
+    size: usize,
+    weight: Option<f64>,
+}
+
+impl ParsedNode {
+    fn new(node: Arc<Node>, size: usize, raw: &Value, sort: &SortConfig) -> ParsedNode {
+        let name = node.read().unwrap().name.clone();
+        let weight = match &sort.mode {
+            SortMode::Attribute(key) => raw[key.as_str()].as_f64(),
+            _ => None,
+        };
+        ParsedNode { node, name, size, weight }
+    }
+
+    // Stable sort, siblings comparing equal keep their source order
+    fn sort(kids: &mut Vec<ParsedNode>, sort: &SortConfig) {
+        let reverse = |order: Ordering| if sort.reverse { order.reverse() } else { order };
+        match &sort.mode {
+            SortMode::Source => {
+                if sort.reverse {
+                    kids.reverse();
+                }
+            },
+            SortMode::Name => kids.sort_by(|a, b| reverse(a.name.cmp(&b.name))),
+            SortMode::Size => kids.sort_by(|a, b| reverse(a.size.cmp(&b.size))),
+            SortMode::Attribute(_) => kids.sort_by(|a, b| {
+                match (a.weight, b.weight) {
+                    (Some(x), Some(y)) => reverse(x.partial_cmp(&y).unwrap_or(Ordering::Equal)),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+            }),
+        }
     }
 }
 
@@ -259,15 +664,23 @@ impl GodswoodProto<NodeProto> for TreeProto {
             nodes_by_depth: Arc::new(RwLock::new(HashMap::new())),
             root: Weak::new(),
             store: store,
+            config: WoodConfig::new(),
         }
     }
 
-    fn parse_from_json(&mut self, raw: &Value) {
-        self.parse(raw);
+    fn set_config(&mut self, config: WoodConfig) {
+        self.config = config;
+    }
+
+    fn parse_from_json(&mut self, raw: &Value) -> Result<(), ParseError> {
+        self.parse(raw)
     }
     fn init_nodes(&mut self) {
         self.init_nodes();
     }
+    fn link_nodes(&mut self) -> Result<(), ParseError> {
+        self.link_nodes()
+    }
     fn get_nodes_by_depths(&self) -> &Godsnodes<NodeProto> {
         &self.nodes_by_depth
     }
@@ -277,6 +690,9 @@ impl GodswoodProto<NodeProto> for TreeProto {
     fn get_root(&self) -> Weak<RwLock<NodeProto>> {
         self.root.clone()
     }
+    fn set_root(&mut self, root: Weak<RwLock<NodeProto>>) {
+        self.root = root;
+    }
     
     fn read_name(&self) -> String {
         let root = self.root.clone();
@@ -286,3 +702,217 @@ impl GodswoodProto<NodeProto> for TreeProto {
 }
 
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn forest(trees: Vec<Value>) -> Result<Forest, ParseError> {
+        let mut config = ForestConfig::new();
+        config.woods = trees;
+        Forest::load(&config)
+    }
+
+    fn node(woods: &Forest, path: &str) -> Arc<Node> {
+        woods.store.get_weak_node(&path.to_string()).and_then(|node| node.upgrade()).unwrap_or_else(|| panic!("no node {}", path))
+    }
+
+    // Names of the children of a node in sibling order
+    fn children(woods: &Forest, path: &str) -> Vec<String> {
+        let node = node(woods, path);
+        let node = node.read().unwrap();
+        node.children.iter().filter_map(|kid| kid.upgrade()).map(|kid| kid.read().unwrap().name.clone()).collect()
+    }
+
+    fn error(trees: Vec<Value>) -> String {
+        forest(trees).err().map(|e| e.to_string()).expect("the woods should not parse")
+    }
+
+    #[test]
+    fn dotted_names_are_escaped_in_paths() {
+        let woods = forest(vec![json!({ "mode": "json", "tree": {
+            "name": "api",
+            "hosts": { "example.com": { "port": 80 }, "example": { "com": { "port": 81 } } },
+        } })]).unwrap();
+
+        let dotted = node(&woods, ".api.hosts.example\\.com");
+        assert_eq!(dotted.read().unwrap().name, "example.com");
+        assert_eq!(dotted.read().unwrap().read_path(), ".api.hosts.example\\.com");
+        // The nested node keeps its own path
+        assert_eq!(node(&woods, ".api.hosts.example.com").read().unwrap().name, "com");
+
+        assert_eq!(GodsnodePath::split(".api.hosts.example\\.com"), vec!["api", "hosts", "example.com"]);
+        assert_eq!(GodsnodePath::split_last(".api.hosts.example\\.com"), (Some(".api.hosts"), "example\\.com"));
+        assert_eq!(GodsnodePath::split_last(".api"), (None, "api"));
+        assert_eq!(GodswoodMeta::parse_app_name(&".a\\.b.c".to_string()), Some("a.b".to_string()));
+    }
+
+    #[test]
+    fn unnamed_woods_are_named_by_file_or_place() {
+        let generic = json!({ "mode": "json", "tree": { "items": [1, 2] } });
+        let woods = forest(vec![generic.clone(), generic.clone(), json!({ "name": "named" })]).unwrap();
+        assert_eq!(woods.names, vec!["wood1", "wood2", "named"]);
+
+        let mut config = ForestConfig::new();
+        config.woods = vec![generic.clone(), generic];
+        config.stems = vec![Some("response".to_string()), None];
+        assert_eq!(Forest::load(&config).unwrap().names, vec!["response", "wood2"]);
+    }
+
+    #[test]
+    fn children_in_object_or_array_form() {
+        let woods = forest(vec![json!({ "name": "app", "children": { "x": { "children": { "y": {} } }, "z": {} } })]).unwrap();
+        assert_eq!(children(&woods, ".app"), vec!["x", "z"]);
+        assert_eq!(children(&woods, ".app.x"), vec!["y"]);
+
+        let woods = forest(vec![json!({ "name": "app", "children": [
+            { "name": "x", "children": [{ "name": "y" }] },
+            { "name": 7 },
+        ] })]).unwrap();
+        assert_eq!(children(&woods, ".app"), vec!["x", "7"]);
+        assert_eq!(children(&woods, ".app.x"), vec!["y"]);
+    }
+
+    #[test]
+    fn key_names_are_configurable() {
+        let woods = forest(vec![json!({
+            "keys": { "name": "id", "display_name": "title", "children": "nodes" },
+            "tree": { "id": "svc", "nodes": [
+                { "id": "a", "title": "Alpha", "nodes": [{ "id": "b" }] },
+                { "id": "c", "name": "ignored" },
+            ] },
+        })]).unwrap();
+        assert_eq!(woods.names, vec!["svc"]);
+        assert_eq!(children(&woods, ".svc"), vec!["a", "c"]);
+        assert_eq!(children(&woods, ".svc.a"), vec!["b"]);
+        let a = node(&woods, ".svc.a");
+        assert_eq!(a.read().unwrap().display_name, "Alpha");
+        // Children are nodes, not attributes
+        assert!(a.read().unwrap().get_attribute("nodes").is_none());
+    }
+
+    #[test]
+    fn array_children_need_a_name() {
+        let message = error(vec![json!({ "name": "app", "children": [{ "name": "a" }, { "title": "anonymous" }] })]);
+        assert_eq!(message, "Child #1 of node app has no usable \"name\" field");
+
+        let message = error(vec![json!({ "name": "app", "children": [{ "name": "" }] })]);
+        assert!(message.starts_with("Child #0 of node app"), "{}", message);
+
+        let message = error(vec![json!({ "keys": { "name": "id" }, "tree": { "id": "app", "children": [{ "name": "a" }] } })]);
+        assert_eq!(message, "Child #0 of node app has no usable \"id\" field");
+
+        let message = error(vec![json!({ "name": "app", "children": 3 })]);
+        assert_eq!(message, "Field \"children\" of node app must be an object or an array");
+    }
+
+    // Children of a wood under the given sort
+    fn sorted(sort: Value) -> Vec<String> {
+        let woods = forest(vec![json!({ "sort": sort, "tree": { "name": "app", "children": {
+            "b": { "cost": 2, "children": { "x": {}, "y": {} } },
+            "d": {},
+            "a": { "cost": 5, "children": { "x": {} } },
+            "c": { "cost": 2 },
+        } } })]).unwrap();
+        children(&woods, ".app")
+    }
+
+    #[test]
+    fn siblings_follow_the_sort_mode() {
+        assert_eq!(sorted(Value::Null), vec!["b", "d", "a", "c"]);
+        assert_eq!(sorted(json!("source")), vec!["b", "d", "a", "c"]);
+        assert_eq!(sorted(json!({ "by": "source", "reverse": true })), vec!["c", "a", "d", "b"]);
+
+        assert_eq!(sorted(json!("name")), vec!["a", "b", "c", "d"]);
+        assert_eq!(sorted(json!({ "by": "name", "reverse": true })), vec!["d", "c", "b", "a"]);
+
+        // Ties keep the source order, in reverse too
+        assert_eq!(sorted(json!("size")), vec!["d", "c", "a", "b"]);
+        assert_eq!(sorted(json!({ "by": "size", "reverse": true })), vec!["b", "a", "d", "c"]);
+
+        // Nodes without the attribute go last either way
+        assert_eq!(sorted(json!({ "by": "attribute", "key": "cost" })), vec!["b", "c", "a", "d"]);
+        assert_eq!(sorted(json!({ "by": "attribute", "key": "cost", "reverse": true })), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn invalid_sorts_are_rejected() {
+        let wood = |sort: Value| json!({ "sort": sort, "tree": { "name": "app" } });
+        assert_eq!(error(vec![wood(json!("height"))]), "Unknown sort mode height");
+        assert_eq!(error(vec![wood(json!({ "by": "attribute" }))]), "Sorting by attribute requires a \"key\"");
+        assert_eq!(error(vec![wood(json!(3))]), "Invalid sort 3");
+    }
+
+    #[test]
+    fn links_reach_woods_listed_later() {
+        let woods = forest(vec![
+            json!({ "name": "web", "children": { "api": { "links": [".db.users"] } } }),
+            json!({ "name": "db", "children": { "users": {} } }),
+        ]).unwrap();
+        let api = node(&woods, ".web.api");
+        let links: Vec<String> = api.read().unwrap().links.iter().filter_map(|link| link.upgrade()).map(|link| link.read().unwrap().read_path()).collect();
+        assert_eq!(links, vec![".db.users"]);
+
+        let message = error(vec![json!({ "name": "web", "children": { "api": { "links": [".db.users"] } } })]);
+        assert_eq!(message, "Node api links to unknown node .db.users");
+    }
+
+    #[test]
+    fn links_creating_a_cycle_are_rejected() {
+        let message = error(vec![json!({ "name": "app", "children": { "a": { "children": { "b": { "links": ["a"] } } } } })]);
+        assert_eq!(message, "Link from node b to .app.a would create a cycle");
+
+        let message = error(vec![
+            json!({ "name": "web", "children": { "api": { "links": [".db.users"] } } }),
+            json!({ "name": "db", "children": { "users": { "links": [".web"] } } }),
+        ]);
+        assert_eq!(message, "Link from node users to .web would create a cycle");
+    }
+
+    #[test]
+    fn shared_nodes_are_placed_once() {
+        let mut config = ForestConfig::new();
+        config.woods = vec![json!({ "name": "app", "children": {
+            "a": { "links": ["shared"] },
+            "b": { "links": ["shared", ".app.shared"] },
+            "shared": {},
+        } })];
+        let woods = Forest::load(&config).unwrap();
+        let placement = config.layout.engine().place(&woods);
+
+        let mut paths: Vec<&String> = placement.nodes.iter().map(|(path, _, _)| path).collect();
+        paths.sort();
+        assert_eq!(paths, vec![".app", ".app.a", ".app.b", ".app.shared"]);
+        // One cross-link from each linking node, the repeated link is dropped
+        assert_eq!(placement.links.len(), 2);
+        assert_eq!(node(&woods, ".app.shared").read().unwrap().parents.len(), 3);
+    }
+
+    #[test]
+    fn subtrees_hold_only_the_descendants() {
+        let woods = forest(vec![
+            json!({ "name": "app", "children": { "a": { "children": { "x": {}, "y": { "children": { "z": {} } } } }, "ab": {}, "b": {} } }),
+            json!({ "name": "other", "children": { "a": {} } }),
+        ]).unwrap();
+
+        let subtree = woods.subtree(".app.a").unwrap();
+        let ordered = subtree.ordered();
+        assert_eq!(ordered.len(), 1);
+        let wood = ordered[0].read().unwrap();
+        assert_eq!(wood.title, "a");
+        assert_eq!(wood.wood.get_root().upgrade().unwrap().read().unwrap().read_path(), ".app.a");
+        let nodes = wood.wood.get_nodes_by_depths();
+        let mut paths: Vec<String> = nodes.read().unwrap().values().flatten()
+            .filter_map(|node| node.upgrade())
+            .map(|node| node.read().unwrap().read_path())
+            .collect();
+        paths.sort();
+        // Nodes keep the paths of the full forest, siblings sharing a prefix stay out
+        assert_eq!(paths, vec![".app.a", ".app.a.x", ".app.a.y", ".app.a.y.z"]);
+
+        assert!(woods.subtree(".app.missing").is_none());
+        assert!(woods.subtree(".nowhere").is_none());
+    }
+}
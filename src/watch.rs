@@ -0,0 +1,77 @@
+use crate::tree::*;
+use crate::misc::*;
+use crate::config::ForestSource;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+
+pub type Reload = Result<Forest, ParseError>;
+
+// Files of the forest and the directories watched for them
+struct Watched {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl Watched {
+    // Editors often replace files instead of writing them, so watch their directories
+    fn update(&mut self, watcher: &mut impl Watcher, files: &[PathBuf]) {
+        self.files = files.iter().map(|file| file.canonicalize().unwrap_or_else(|_| file.clone())).collect();
+        let dirs: HashSet<PathBuf> = self.files.iter().filter_map(|file| file.parent()).map(|dir| dir.to_path_buf()).collect();
+        for dir in self.dirs.difference(&dirs) {
+            let _ = watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.dirs) {
+            if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                warn!("Failed to watch {}: {}", dir.display(), e);
+            }
+        }
+        self.dirs = dirs;
+    }
+}
+
+// Watch the files of the forest, the forest is read and parsed again in the background
+// whenever one of them changes
+pub fn watch(source: ForestSource, files: Vec<PathBuf>) -> Receiver<Reload> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let (events_sender, events) = channel();
+        let mut watcher = match watcher(events_sender, Duration::from_millis(200)) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                warn!("Failed to watch input files: {}", e);
+                return;
+            }
+        };
+
+        let mut watched = Watched { files: HashSet::new(), dirs: HashSet::new() };
+        watched.update(&mut watcher, &files);
+
+        for event in events.iter() {
+            let path = match event {
+                DebouncedEvent::Write(path) | DebouncedEvent::Create(path) | DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+            if !watched.files.contains(&path) {
+                continue;
+            }
+
+            info!("{} changed, reloading", path.display());
+            let woods = source.read().and_then(|forest| {
+                // The config may list other files now
+                watched.update(&mut watcher, &forest.files);
+                Forest::load(&forest)
+            });
+            if sender.send(woods).is_err() {
+                // Viewer is gone
+                break;
+            }
+        }
+    });
+    receiver
+}